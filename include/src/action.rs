@@ -0,0 +1,804 @@
+extern crate rand;
+
+use std::fmt;
+
+use action::rand::Rng;
+use specs::{EntitiesRes, Entity, Join, ReadStorage, WriteStorage};
+
+use bus::{EventBus, GameEvent};
+use component::{Position, BaseEntity, Blocking, Item, Key, Inventory, Fighter, Healing, Ranged, Ammo, Food, Hunger};
+use event::Event;
+use faction::{Faction, Relation, Relationships};
+use fov::{line_of_fire, LineOfFire};
+use map::{Map, TileKind};
+use spatial::SpatialIndex;
+use status::{EffectKind, StatusEffects};
+
+/// How long an ordinary action takes.
+pub const TURN_COST: u32 = 100;
+
+/// Something an actor wants to do this turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Step to a neighbouring tile. Bumping into a closed door turns into
+    /// `OpenDoor`, and bumping into someone turns into `Attack`, `Swap` or
+    /// `Talk` depending on how the two get on.
+    Move { dx: i32, dy: i32 },
+    Attack { target: Entity },
+    /// Trade places with a neighbour.
+    Swap { target: Entity },
+    Talk { target: Entity },
+    OpenDoor { x: i32, y: i32 },
+    /// Close every open door next to the actor.
+    CloseDoors,
+    /// Pick up everything the actor is standing on.
+    PickUp,
+    /// Use the item in this inventory slot.
+    Use { slot: usize },
+    /// Shoot or throw the ranged item in this inventory slot at a tile.
+    Fire { slot: usize, x: i32, y: i32 },
+    /// Fire the first ranged item carried at the nearest hostile fighter it can hit.
+    /// Turns into `Fire`.
+    FireAtNearest,
+    Wait,
+}
+
+impl Action {
+    /// The action the player means by an event, if it is one which happens in the world.
+    pub fn from_event(event: &Event) -> Option<Action> {
+        match *event {
+            Event::Movement((dx, dy)) => Some(Action::Move { dx, dy }),
+            Event::CloseDoors => Some(Action::CloseDoors),
+            Event::PickUp => Some(Action::PickUp),
+            Event::Use(slot) => Some(Action::Use { slot }),
+            Event::Fire => Some(Action::FireAtNearest),
+            Event::Wait => Some(Action::Wait),
+            Event::NextTheme | Event::Quit => None,
+        }
+    }
+}
+
+/// Why an action couldn't be done. Nothing in the world has changed when one of these is returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActionError {
+    /// The actor has no position, so can't do anything.
+    NotOnMap,
+    Wall,
+    /// Something which can't be fought is in the way.
+    Blocked(String),
+    /// The door needs a key the actor isn't carrying.
+    Locked,
+    NoDoor,
+    TooFar,
+    NothingToAttack,
+    /// Nobody is there to swap places or talk with.
+    NobodyThere,
+    NothingToClose,
+    NothingToPickUp,
+    CantCarry,
+    NoSuchItem(usize),
+    CantUse(String),
+    AlreadyHealthy,
+    /// Nothing carried can be shot or thrown.
+    NothingToFire,
+    CantFire(String),
+    NoAmmo,
+    NoTarget,
+    /// Something opaque is right in the way.
+    NoLineOfFire,
+    /// A confused actor lurched into what is given here. Unlike other failures this takes the turn.
+    Stumbled(Box<ActionError>),
+}
+
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ActionError::NotOnMap => write!(f, "You aren't anywhere."),
+            ActionError::Wall => write!(f, "There is a wall in the way."),
+            ActionError::Blocked(ref name) => write!(f, "The {} is in the way.", name),
+            ActionError::Locked => write!(f, "The door is locked."),
+            ActionError::NoDoor => write!(f, "There is no door there."),
+            ActionError::TooFar => write!(f, "That is too far away."),
+            ActionError::NothingToAttack => write!(f, "There is nothing there to attack."),
+            ActionError::NobodyThere => write!(f, "There is nobody there."),
+            ActionError::NothingToClose => write!(f, "There is no open door to close."),
+            ActionError::NothingToPickUp => write!(f, "There is nothing here to pick up."),
+            ActionError::CantCarry => write!(f, "You can't carry anything."),
+            ActionError::NoSuchItem(slot) => write!(f, "You have nothing in slot {}.", slot + 1),
+            ActionError::CantUse(ref name) => write!(f, "You can't use the {}.", name),
+            ActionError::AlreadyHealthy => write!(f, "You are already at full health."),
+            ActionError::NothingToFire => write!(f, "You have nothing to shoot or throw."),
+            ActionError::CantFire(ref name) => write!(f, "You can't shoot or throw the {}.", name),
+            ActionError::NoAmmo => write!(f, "You are out of ammunition."),
+            ActionError::NoTarget => write!(f, "There is nothing in range to hit."),
+            ActionError::NoLineOfFire => write!(f, "There is no clear shot."),
+            ActionError::Stumbled(ref why) => write!(f, "You stumble. {}", why),
+        }
+    }
+}
+
+/// What happened when an actor tried to act.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionResult {
+    pub actor: Entity,
+    /// The action which was tried, after bumps were worked out.
+    pub action: Action,
+    pub outcome: Result<(), ActionError>,
+    /// Time the action took. Failed actions take none, so the actor can try something else,
+    /// except for stumbles.
+    pub cost: u32,
+}
+
+impl ActionResult {
+    pub fn succeeded(&self) -> bool {
+        self.outcome.is_ok()
+    }
+
+    pub fn turn_spent(&self) -> bool {
+        self.cost > 0
+    }
+}
+
+/// The parts of the world actions look at and change.
+pub struct ActionContext<'c, 'a: 'c> {
+    pub entities: &'c EntitiesRes,
+    pub map: &'c mut Map,
+    pub index: &'c mut SpatialIndex,
+    pub positions: &'c mut WriteStorage<'a, Position>,
+    pub blocking: &'c ReadStorage<'a, Blocking>,
+    pub names: &'c ReadStorage<'a, BaseEntity>,
+    pub items: &'c ReadStorage<'a, Item>,
+    pub keys: &'c ReadStorage<'a, Key>,
+    pub inventories: &'c mut WriteStorage<'a, Inventory>,
+    pub fighters: &'c mut WriteStorage<'a, Fighter>,
+    pub healing: &'c ReadStorage<'a, Healing>,
+    pub effects: &'c ReadStorage<'a, StatusEffects>,
+    pub ranged: &'c ReadStorage<'a, Ranged>,
+    pub ammo: &'c mut WriteStorage<'a, Ammo>,
+    pub food: &'c ReadStorage<'a, Food>,
+    pub hunger: &'c mut WriteStorage<'a, Hunger>,
+    pub factions: &'c ReadStorage<'a, Faction>,
+    pub relationships: &'c Relationships,
+    /// Where everything the actions do is announced.
+    pub bus: &'c mut EventBus,
+}
+
+impl<'c, 'a> ActionContext<'c, 'a> {
+    fn position(&self, ent: Entity) -> Result<(i32, i32), ActionError> {
+        self.positions.get(ent).map(|pos| (pos.x, pos.y)).ok_or(ActionError::NotOnMap)
+    }
+
+    fn name(&self, ent: Entity) -> String {
+        self.names.get(ent).map_or_else(|| String::from("something"), |b| b.name.clone())
+    }
+
+    fn has_key(&self, ent: Entity, id: u32) -> bool {
+        self.inventories.get(ent).is_some_and(|inv| {
+            inv.items.iter().any(|&item| self.keys.get(item).is_some_and(|k| k.id == id))
+        })
+    }
+
+    /// Open doors next to `(x, y)` with nothing standing in them.
+    fn closable_doors(&self, x: i32, y: i32) -> Vec<(i32, i32)> {
+        self.map.neighbours(x, y)
+            .filter(|&(nx, ny)| self.map.get(nx, ny).is_some_and(|t| t.kind == TileKind::DoorOpen))
+            .filter(|&(nx, ny)| self.index.entities_at(nx, ny).is_empty())
+            .collect()
+    }
+
+    fn items_at(&self, x: i32, y: i32) -> Vec<Entity> {
+        self.index.entities_at(x, y).iter()
+            .cloned()
+            .filter(|&item| self.items.get(item).is_some())
+            .collect()
+    }
+
+    fn carried(&self, ent: Entity) -> &[Entity] {
+        self.inventories.get(ent).map_or(&[], |inv| &inv.items)
+    }
+
+    /// The first ammunition `ent` is carrying which isn't used up.
+    fn ammo_for(&self, ent: Entity) -> Option<Entity> {
+        self.carried(ent).iter().cloned().find(|&item| self.ammo.get(item).is_some_and(|a| a.count > 0))
+    }
+
+    fn shot(&self, from: (i32, i32), to: (i32, i32), item: Entity) -> LineOfFire {
+        let range = self.ranged.get(item).map_or(0, |r| r.range);
+        line_of_fire(self.map, self.index, from, to, range)
+    }
+
+    /// A living fighter other than `actor` on the tile.
+    fn fighter_at(&self, actor: Entity, x: i32, y: i32) -> Option<Entity> {
+        self.index.entities_at(x, y).iter()
+            .cloned()
+            .find(|&ent| ent != actor && self.fighters.get(ent).is_some_and(|f| f.hp > 0))
+    }
+
+    /// Whoever is in the way on the tile: a living fighter if there is one,
+    /// or else anything blocking.
+    fn occupant(&self, actor: Entity, x: i32, y: i32) -> Option<Entity> {
+        self.fighter_at(actor, x, y).or_else(|| {
+            self.index.entities_at(x, y).iter()
+                .cloned()
+                .find(|&ent| ent != actor && self.blocking.get(ent).is_some())
+        })
+    }
+
+    /// How `actor` feels about `other`.
+    pub fn relation(&self, actor: Entity, other: Entity) -> Relation {
+        self.relationships.between(self.factions.get(actor), self.factions.get(other))
+    }
+
+    /// Hurt `target` on behalf of `source`, announcing it if it dies.
+    fn damage(&mut self, source: Entity, target: Entity, power: i32) {
+        let (amount, hp) = {
+            let fighter = self.fighters.get_mut(target).unwrap();
+            let amount = (power - fighter.defense).max(0);
+            fighter.hp -= amount;
+            (amount, fighter.hp)
+        };
+        info!("{} hits {} ({} hp left)", self.name(source), self.name(target), hp);
+        self.bus.emit(GameEvent::Damaged { target, source, amount });
+        if hp <= 0 {
+            self.bus.emit(GameEvent::Died { entity: target });
+        }
+    }
+
+    /// Check whether `actor` can do `action`, without changing anything.
+    /// Returns the action which would actually happen, with bumps worked out.
+    pub fn validate(&self, actor: Entity, action: Action) -> Result<Action, ActionError> {
+        let (x, y) = self.position(actor)?;
+        match action {
+            Action::Move { dx, dy } => {
+                let (nx, ny) = (x + dx, y + dy);
+                if self.map.get(nx, ny).is_some_and(|t| t.kind == TileKind::DoorClosed) {
+                    return self.validate(actor, Action::OpenDoor { x: nx, y: ny });
+                }
+                if let Some(target) = self.occupant(actor, nx, ny) {
+                    match self.relation(actor, target) {
+                        Relation::Friendly => return self.validate(actor, Action::Swap { target }),
+                        Relation::Neutral => return self.validate(actor, Action::Talk { target }),
+                        // Only fighters attack; anything else just finds its way blocked.
+                        Relation::Hostile => if self.fighters.get(actor).is_some()
+                                                && self.fighter_at(actor, nx, ny) == Some(target) {
+                            return self.validate(actor, Action::Attack { target });
+                        },
+                    }
+                }
+                if !self.map.is_walkable(nx, ny) {
+                    return Err(ActionError::Wall);
+                }
+                if self.index.is_blocked(nx, ny) {
+                    let blocker = self.index.entities_at(nx, ny).iter()
+                        .cloned()
+                        .find(|&ent| self.blocking.get(ent).is_some());
+                    return Err(ActionError::Blocked(blocker.map_or_else(|| String::from("something"),
+                                                                        |ent| self.name(ent))));
+                }
+                Ok(action)
+            },
+            Action::Attack { target } => {
+                // Anything at 0 hp is dead, even if it hasn't been cleared away yet.
+                if self.fighters.get(actor).is_none() || self.fighters.get(target).is_none_or(|f| f.hp <= 0) {
+                    return Err(ActionError::NothingToAttack);
+                }
+                let (tx, ty) = self.position(target).map_err(|_| ActionError::NothingToAttack)?;
+                if (tx - x).abs() > 1 || (ty - y).abs() > 1 {
+                    return Err(ActionError::TooFar);
+                }
+                Ok(action)
+            },
+            Action::Swap { target } | Action::Talk { target } => {
+                if target == actor {
+                    return Err(ActionError::NobodyThere);
+                }
+                let (tx, ty) = self.position(target).map_err(|_| ActionError::NobodyThere)?;
+                if (tx - x).abs() > 1 || (ty - y).abs() > 1 {
+                    return Err(ActionError::TooFar);
+                }
+                Ok(action)
+            },
+            Action::OpenDoor { x: dx, y: dy } => {
+                if (dx - x).abs() > 1 || (dy - y).abs() > 1 {
+                    return Err(ActionError::TooFar);
+                }
+                match self.map.get(dx, dy) {
+                    Some(tile) if tile.kind == TileKind::DoorClosed => match tile.lock {
+                        Some(id) if !self.has_key(actor, id) => Err(ActionError::Locked),
+                        _ => Ok(action),
+                    },
+                    _ => Err(ActionError::NoDoor),
+                }
+            },
+            Action::CloseDoors => {
+                if self.closable_doors(x, y).is_empty() {
+                    return Err(ActionError::NothingToClose);
+                }
+                Ok(action)
+            },
+            Action::PickUp => {
+                if self.inventories.get(actor).is_none() {
+                    return Err(ActionError::CantCarry);
+                }
+                if self.items_at(x, y).is_empty() {
+                    return Err(ActionError::NothingToPickUp);
+                }
+                Ok(action)
+            },
+            Action::Use { slot } => {
+                let item = self.inventories.get(actor)
+                    .and_then(|inv| inv.items.get(slot).cloned())
+                    .ok_or(ActionError::NoSuchItem(slot))?;
+                // Anything which gets hungry can always eat.
+                if self.food.get(item).is_some() {
+                    return match self.hunger.get(actor) {
+                        Some(_) => Ok(action),
+                        None => Err(ActionError::CantUse(self.name(item))),
+                    };
+                }
+                if self.healing.get(item).is_none() {
+                    return Err(ActionError::CantUse(self.name(item)));
+                }
+                match self.fighters.get(actor) {
+                    Some(fighter) if fighter.hp < fighter.max_hp => Ok(action),
+                    Some(_) => Err(ActionError::AlreadyHealthy),
+                    None => Err(ActionError::CantUse(self.name(item))),
+                }
+            },
+            Action::Fire { slot, x: tx, y: ty } => {
+                let item = self.carried(actor).get(slot).cloned().ok_or(ActionError::NoSuchItem(slot))?;
+                let ranged = self.ranged.get(item).ok_or_else(|| ActionError::CantFire(self.name(item)))?;
+                if (tx - x).abs().max((ty - y).abs()) > ranged.range {
+                    return Err(ActionError::TooFar);
+                }
+                if !ranged.thrown && self.ammo_for(actor).is_none() {
+                    return Err(ActionError::NoAmmo);
+                }
+                if (tx, ty) == (x, y) || self.shot((x, y), (tx, ty), item).path.is_empty() {
+                    return Err(ActionError::NoLineOfFire);
+                }
+                Ok(action)
+            },
+            Action::FireAtNearest => {
+                let slot = self.carried(actor).iter()
+                    .position(|&item| self.ranged.get(item).is_some())
+                    .ok_or(ActionError::NothingToFire)?;
+                let item = self.carried(actor)[slot];
+                // Only count targets the shot would actually reach.
+                let target = (self.entities, &*self.positions, &*self.fighters).join()
+                    .filter(|&(ent, _, fighter)| ent != actor && fighter.hp > 0)
+                    .filter(|&(ent, _, _)| self.relation(actor, ent) == Relation::Hostile)
+                    .map(|(_, pos, _)| (pos.x, pos.y))
+                    .filter(|&to| self.shot((x, y), to, item).hit == Some(to))
+                    .min_by_key(|&(tx, ty)| (tx - x).abs().max((ty - y).abs()))
+                    .ok_or(ActionError::NoTarget)?;
+                self.validate(actor, Action::Fire { slot, x: target.0, y: target.1 })
+            },
+            Action::Wait => Ok(action),
+        }
+    }
+
+    /// Do an action `validate` has already allowed.
+    fn execute(&mut self, actor: Entity, action: Action) {
+        let (x, y) = match self.position(actor) {
+            Ok(pos) => pos,
+            Err(_) => return,
+        };
+        match action {
+            Action::Move { dx, dy } => {
+                let (nx, ny) = (x + dx, y + dy);
+                let pos = self.positions.get_mut(actor).unwrap();
+                pos.x = nx;
+                pos.y = ny;
+                // Keep the index up to date, so later actors this tick see this one.
+                self.index.move_entity(actor, (x, y), (nx, ny), self.blocking.get(actor).is_some());
+                self.bus.emit(GameEvent::Moved { entity: actor, from: (x, y), to: (nx, ny) });
+            },
+            Action::Attack { target } => {
+                let power = self.fighters.get(actor).map_or(0, |f| f.power);
+                self.damage(actor, target, power);
+            },
+            Action::Swap { target } => {
+                let (tx, ty) = self.position(target).unwrap();
+                *self.positions.get_mut(actor).unwrap() = Position { x: tx, y: ty };
+                *self.positions.get_mut(target).unwrap() = Position { x, y };
+                self.index.move_entity(actor, (x, y), (tx, ty), self.blocking.get(actor).is_some());
+                self.index.move_entity(target, (tx, ty), (x, y), self.blocking.get(target).is_some());
+                self.bus.emit(GameEvent::Moved { entity: actor, from: (x, y), to: (tx, ty) });
+                self.bus.emit(GameEvent::Moved { entity: target, from: (tx, ty), to: (x, y) });
+            },
+            Action::Talk { target } => {
+                info!("{} talks to {}", self.name(actor), self.name(target));
+                self.bus.emit(GameEvent::Talked { entity: actor, target });
+            },
+            Action::OpenDoor { x: dx, y: dy } => {
+                if let Some(id) = self.map.get(dx, dy).and_then(|tile| tile.lock) {
+                    self.map.unlock(dx, dy, id);
+                    info!("Unlocked the door.");
+                }
+                self.map.open_door(dx, dy);
+            },
+            Action::CloseDoors => {
+                for (nx, ny) in self.closable_doors(x, y) {
+                    self.map.close_door(nx, ny);
+                    info!("Closed the door at {:?}", (nx, ny));
+                }
+            },
+            Action::PickUp => {
+                for item in self.items_at(x, y) {
+                    self.positions.remove(item);
+                    self.index.remove(item, x, y, self.blocking.get(item).is_some());
+                    self.inventories.get_mut(actor).unwrap().items.push(item);
+                    info!("Picked up {}", self.name(item));
+                    self.bus.emit(GameEvent::PickedUp { entity: actor, item });
+                }
+            },
+            Action::Use { slot } => {
+                let item = self.inventories.get_mut(actor).unwrap().items.remove(slot);
+                if let Some(food) = self.food.get(item) {
+                    let hunger = self.hunger.get_mut(actor).unwrap();
+                    let before = hunger.state();
+                    hunger.food = (hunger.food + food.amount).min(hunger.max_food);
+                    if hunger.state() != before {
+                        self.bus.emit(GameEvent::HungerChanged { entity: actor, state: hunger.state() });
+                    }
+                } else {
+                    let amount = self.healing.get(item).map_or(0, |h| h.amount);
+                    let fighter = self.fighters.get_mut(actor).unwrap();
+                    fighter.hp = (fighter.hp + amount).min(fighter.max_hp);
+                }
+                let _ = self.entities.delete(item);
+            },
+            Action::Fire { slot, x: tx, y: ty } => {
+                let item = self.carried(actor)[slot];
+                let shot = self.shot((x, y), (tx, ty), item);
+                self.bus.emit(GameEvent::Fired { entity: actor, path: shot.path.clone() });
+                let (damage, thrown) = self.ranged.get(item).map_or((0, false), |r| (r.damage, r.thrown));
+                if let Some((hx, hy)) = shot.hit {
+                    if let Some(target) = self.fighter_at(actor, hx, hy) {
+                        self.damage(actor, target, damage);
+                    }
+                }
+                if thrown {
+                    // It lands wherever it stopped.
+                    let (lx, ly) = *shot.path.last().unwrap();
+                    self.inventories.get_mut(actor).unwrap().items.remove(slot);
+                    self.positions.insert(item, Position { x: lx, y: ly });
+                    self.index.insert(item, lx, ly, self.blocking.get(item).is_some());
+                } else if let Some(ammo) = self.ammo_for(actor) {
+                    let left = {
+                        let ammo = self.ammo.get_mut(ammo).unwrap();
+                        ammo.count -= 1;
+                        ammo.count
+                    };
+                    if left == 0 {
+                        info!("Used up the {}", self.name(ammo));
+                        self.inventories.get_mut(actor).unwrap().items.retain(|&i| i != ammo);
+                        let _ = self.entities.delete(ammo);
+                    }
+                }
+            },
+            // Always resolved into `Fire` by `validate`.
+            Action::FireAtNearest => {},
+            Action::Wait => {},
+        }
+    }
+
+    /// Validate and then, if it is allowed, execute an action.
+    pub fn perform(&mut self, actor: Entity, action: Action) -> ActionResult {
+        match self.validate(actor, action) {
+            Ok(resolved) => {
+                self.execute(actor, resolved);
+                let cost = self.effects.get(actor).map_or(TURN_COST, |e| e.action_cost(TURN_COST));
+                ActionResult { actor, action: resolved, outcome: Ok(()), cost }
+            },
+            Err(e) => {
+                info!("{:?} failed: {}", action, e);
+                ActionResult { actor, action, outcome: Err(e), cost: 0 }
+            },
+        }
+    }
+
+    /// `perform`, except that confused actors who try to move lurch off in a
+    /// random direction. Lurching into a wall or something in the way still
+    /// takes the turn, so confusion can't be walked off for free.
+    pub fn perform_confused<R: Rng>(&mut self, actor: Entity, action: Action, rng: &mut R) -> ActionResult {
+        let (dx, dy) = match (action, self.effects.get(actor)) {
+            (Action::Move { dx, dy }, Some(effects)) if effects.has(EffectKind::Confusion) => {
+                effects.stumble((dx, dy), rng)
+            },
+            _ => return self.perform(actor, action),
+        };
+        let mut result = self.perform(actor, Action::Move { dx, dy });
+        if let Err(e @ ActionError::Wall) | Err(e @ ActionError::Blocked(_)) = result.outcome.clone() {
+            result.outcome = Err(ActionError::Stumbled(Box::new(e)));
+            result.cost = self.effects.get(actor).map_or(TURN_COST, |fx| fx.action_cost(TURN_COST));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{Entity, Join, RunNow, World};
+    use action::{Action, ActionContext, ActionError, ActionResult};
+    use bus::{EventBus, EventKind, GameEvent};
+    use component::{Position, BaseEntity, Blocking, Item, Key, Inventory, Fighter, Healing, Ranged, Ammo, Food,
+                    Hunger, HungerState};
+    use faction::{Faction, Relation, Relationships};
+    use map::{Map, TileKind};
+    use map_utils::seeded_rng;
+    use spatial::SpatialIndex;
+    use spawn::spawn;
+    use status::{EffectKind, StatusEffects};
+    use system::{DeathSystem, SpatialIndexSystem};
+    use testing::level;
+
+    /// A fighter standing at `x`, `y`.
+    fn hero(world: &mut World, x: i32, y: i32) -> Entity {
+        world.create_entity()
+            .with(Position { x, y })
+            .with(Blocking)
+            .with(Inventory::default())
+            .with(Fighter::new(20, 5, 1))
+            .build()
+    }
+
+    fn with_context<R, F: FnOnce(&mut ActionContext) -> R>(world: &World, f: F) -> R {
+        SpatialIndexSystem.run_now(&world.res);
+        let entities = world.entities();
+        let mut map = world.write_resource::<Map>();
+        let mut index = world.write_resource::<SpatialIndex>();
+        let mut bus = world.write_resource::<EventBus>();
+        let mut ctx = ActionContext {
+            entities: &entities,
+            map: &mut map,
+            index: &mut index,
+            positions: &mut world.write::<Position>(),
+            blocking: &world.read::<Blocking>(),
+            names: &world.read::<BaseEntity>(),
+            items: &world.read::<Item>(),
+            keys: &world.read::<Key>(),
+            inventories: &mut world.write::<Inventory>(),
+            fighters: &mut world.write::<Fighter>(),
+            healing: &world.read::<Healing>(),
+            effects: &world.read::<StatusEffects>(),
+            ranged: &world.read::<Ranged>(),
+            ammo: &mut world.write::<Ammo>(),
+            food: &world.read::<Food>(),
+            hunger: &mut world.write::<Hunger>(),
+            factions: &world.read::<Faction>(),
+            relationships: &world.read_resource::<Relationships>(),
+            bus: &mut bus,
+        };
+        f(&mut ctx)
+    }
+
+    fn fighter_at(world: &World, x: i32, y: i32) -> Entity {
+        let entities = world.entities();
+        let (positions, fighters) = (world.read::<Position>(), world.read::<Fighter>());
+        (&*entities, &positions, &fighters).join()
+            .find(|&(_, pos, _)| (pos.x, pos.y) == (x, y))
+            .map(|(ent, _, _)| ent)
+            .unwrap()
+    }
+
+    fn perform(world: &mut World, actor: Entity, action: Action) -> ActionResult {
+        let result = with_context(world, |ctx| ctx.perform(actor, action));
+        world.maintain();
+        result
+    }
+
+    #[test]
+    fn bumps_turn_into_attacks_and_doors() {
+        let mut world = level("#####\n#@o+#\n#.L.#\n#####");
+        let hero = hero(&mut world, 1, 1);
+        let orc = fighter_at(&world, 2, 1);
+        let watcher = world.write_resource::<EventBus>().subscribe(EventKind::all());
+        let mut deaths = DeathSystem::new(&mut world.write_resource::<EventBus>());
+
+        // Working out a bump doesn't change anything.
+        let resolved = with_context(&world, |ctx| ctx.validate(hero, Action::Move { dx: 1, dy: 0 }));
+        assert_eq!(resolved, Ok(Action::Attack { target: orc }));
+        assert_eq!(world.read::<Fighter>().get(orc).unwrap().hp, 10);
+
+        // 10 hp and no defense against 5 power: two hits.
+        assert!(perform(&mut world, hero, Action::Move { dx: 1, dy: 0 }).turn_spent());
+        assert_eq!(world.read::<Fighter>().get(orc).unwrap().hp, 5);
+        perform(&mut world, hero, Action::Move { dx: 1, dy: 0 });
+        assert_eq!(world.write_resource::<EventBus>().read(watcher), vec![
+            GameEvent::Damaged { target: orc, source: hero, amount: 5 },
+            GameEvent::Damaged { target: orc, source: hero, amount: 5 },
+            GameEvent::Died { entity: orc },
+        ]);
+        // Dying is announced; clearing the body away is up to `DeathSystem`.
+        assert!(world.is_alive(orc));
+        deaths.run_now(&world.res);
+        world.maintain();
+        assert!(!world.is_alive(orc));
+
+        perform(&mut world, hero, Action::Move { dx: 1, dy: 0 });
+        let result = perform(&mut world, hero, Action::Move { dx: 1, dy: 0 });
+        assert_eq!(result.action, Action::OpenDoor { x: 3, y: 1 });
+        assert_eq!(world.read_resource::<Map>().get(3, 1).unwrap().kind, TileKind::DoorOpen);
+
+        let result = perform(&mut world, hero, Action::Move { dx: 0, dy: 1 });
+        assert_eq!(result.outcome, Err(ActionError::Locked));
+        assert_eq!(result.cost, 0);
+    }
+
+    #[test]
+    fn failures_explain_themselves_and_cost_nothing() {
+        let mut world = level("###\n#@#\n###");
+        let hero = hero(&mut world, 1, 1);
+        for &(action, ref error) in &[
+            (Action::Move { dx: 0, dy: 1 }, ActionError::Wall),
+            (Action::CloseDoors, ActionError::NothingToClose),
+            (Action::PickUp, ActionError::NothingToPickUp),
+            (Action::Use { slot: 0 }, ActionError::NoSuchItem(0)),
+            (Action::OpenDoor { x: 1, y: 0 }, ActionError::NoDoor),
+        ] {
+            let result = perform(&mut world, hero, action);
+            assert_eq!(result.outcome.as_ref(), Err(error));
+            assert!(!result.turn_spent());
+        }
+        assert_eq!(ActionError::NoSuchItem(0).to_string(), "You have nothing in slot 1.");
+        assert!(perform(&mut world, hero, Action::Wait).succeeded());
+
+        // Haste makes actions quicker, and failures still cost nothing.
+        let mut effects = StatusEffects::new();
+        effects.add(EffectKind::Haste, 3);
+        world.write::<StatusEffects>().insert(hero, effects);
+        assert_eq!(perform(&mut world, hero, Action::Wait).cost, 50);
+        assert_eq!(perform(&mut world, hero, Action::PickUp).cost, 0);
+    }
+
+    #[test]
+    fn stumbling_into_walls_takes_the_turn() {
+        let mut world = level("###\n#@#\n###");
+        let hero = hero(&mut world, 1, 1);
+        let mut effects = StatusEffects::new();
+        effects.add(EffectKind::Confusion, 5);
+        world.write::<StatusEffects>().insert(hero, effects);
+
+        // Boxed in, so every lurch hits a wall.
+        let mut rng = seeded_rng(0);
+        let east = Action::Move { dx: 1, dy: 0 };
+        for _ in 0..5 {
+            let result = with_context(&world, |ctx| ctx.perform_confused(hero, east, &mut rng));
+            assert_eq!(result.outcome, Err(ActionError::Stumbled(Box::new(ActionError::Wall))));
+            assert!(result.turn_spent());
+        }
+        assert_eq!(ActionError::Stumbled(Box::new(ActionError::Wall)).to_string(),
+                   "You stumble. There is a wall in the way.");
+
+        // Anything other than moving goes ahead as usual.
+        let result = with_context(&world, |ctx| ctx.perform_confused(hero, Action::PickUp, &mut rng));
+        assert_eq!(result.outcome, Err(ActionError::NothingToPickUp));
+        assert!(!result.turn_spent());
+    }
+
+    #[test]
+    fn shots_hit_the_nearest_target_and_use_up_ammo() {
+        let mut world = level("########\n#@..g.o#\n########");
+        let hero = hero(&mut world, 1, 1);
+        let goblin = fighter_at(&world, 4, 1);
+        let watcher = world.write_resource::<EventBus>().subscribe(&[EventKind::Fired]);
+        assert_eq!(perform(&mut world, hero, Action::FireAtNearest).outcome, Err(ActionError::NothingToFire));
+
+        spawn(&mut world, "Shortbow", 1, 1).unwrap();
+        perform(&mut world, hero, Action::PickUp);
+        assert_eq!(perform(&mut world, hero, Action::FireAtNearest).outcome, Err(ActionError::NoAmmo));
+        let arrows = spawn(&mut world, "Arrows", 1, 1).unwrap();
+        world.write::<Ammo>().get_mut(arrows).unwrap().count = 2;
+        perform(&mut world, hero, Action::PickUp);
+
+        let result = perform(&mut world, hero, Action::FireAtNearest);
+        assert_eq!(result.action, Action::Fire { slot: 0, x: 4, y: 1 });
+        assert_eq!(world.read::<Fighter>().get(goblin).unwrap().hp, 1);
+        assert_eq!(world.write_resource::<EventBus>().read(watcher), vec![
+            GameEvent::Fired { entity: hero, path: vec![(2, 1), (3, 1), (4, 1)] },
+        ]);
+
+        // Aiming past the goblin still hits it first.
+        assert!(perform(&mut world, hero, Action::Fire { slot: 0, x: 6, y: 1 }).succeeded());
+        assert_eq!(world.read::<Fighter>().get(goblin).unwrap().hp, -4);
+        assert!(!world.is_alive(arrows));
+        assert_eq!(world.read::<Inventory>().get(hero).unwrap().items.len(), 1);
+        let result = perform(&mut world, hero, Action::Fire { slot: 0, x: 6, y: 1 });
+        assert_eq!(result.outcome, Err(ActionError::NoAmmo));
+    }
+
+    #[test]
+    fn bumps_and_shots_depend_on_who_is_there() {
+        let mut world = level("#######\n#$@o.g#\n#######");
+        let hero = hero(&mut world, 2, 1);
+        world.write::<Faction>().insert(hero, Faction::Player);
+        let orc = fighter_at(&world, 3, 1);
+        let glyph = with_context(&world, |ctx| ctx.index.entities_at(1, 1)[0]);
+        world.write_resource::<Relationships>().set(Faction::Player, Faction::Monsters, Relation::Neutral);
+        world.write_resource::<Relationships>().set(Faction::Player, Faction::Strangers, Relation::Friendly);
+        let watcher = world.write_resource::<EventBus>().subscribe(&[EventKind::Moved, EventKind::Talked]);
+
+        let result = perform(&mut world, hero, Action::Move { dx: 1, dy: 0 });
+        assert_eq!(result.action, Action::Talk { target: orc });
+        assert!(result.turn_spent());
+        assert_eq!(perform(&mut world, hero, Action::Move { dx: -1, dy: 0 }).action, Action::Swap { target: glyph });
+        assert_eq!(world.write_resource::<EventBus>().read(watcher), vec![
+            GameEvent::Talked { entity: hero, target: orc },
+            GameEvent::Moved { entity: hero, from: (2, 1), to: (1, 1) },
+            GameEvent::Moved { entity: glyph, from: (1, 1), to: (2, 1) },
+        ]);
+
+        // Only hostiles are shot at, even when a neutral is nearer.
+        spawn(&mut world, "Dagger", 1, 1).unwrap();
+        perform(&mut world, hero, Action::PickUp);
+        world.write_resource::<Relationships>().set(Faction::Player, Faction::Monsters, Relation::Hostile);
+        world.write_resource::<Relationships>().set(Faction::Player, Faction::Strangers, Relation::Neutral);
+        assert_eq!(perform(&mut world, hero, Action::FireAtNearest).outcome, Err(ActionError::NoTarget));
+        assert_eq!(perform(&mut world, hero, Action::Move { dx: 1, dy: 0 }).action, Action::Talk { target: glyph });
+
+        world.write_resource::<Relationships>().set(Faction::Player, Faction::Strangers, Relation::Friendly);
+        perform(&mut world, hero, Action::Move { dx: 1, dy: 0 });
+        assert_eq!(perform(&mut world, hero, Action::Move { dx: 1, dy: 0 }).action, Action::Attack { target: orc });
+        let result = perform(&mut world, hero, Action::FireAtNearest);
+        assert_eq!(result.action, Action::Fire { slot: 0, x: 3, y: 1 });
+        assert_eq!(world.read::<Fighter>().get(orc).unwrap().hp, 1);
+    }
+
+    #[test]
+    fn thrown_things_land_where_they_stop() {
+        let mut world = level("#######\n#@...o#\n#######");
+        let hero = hero(&mut world, 1, 1);
+        let orc = fighter_at(&world, 5, 1);
+        let dagger = spawn(&mut world, "Dagger", 1, 1).unwrap();
+        perform(&mut world, hero, Action::PickUp);
+
+        let result = perform(&mut world, hero, Action::Fire { slot: 0, x: 6, y: 1 });
+        assert_eq!(result.outcome, Err(ActionError::TooFar));
+        assert!(perform(&mut world, hero, Action::Fire { slot: 0, x: 5, y: 1 }).succeeded());
+        assert_eq!(world.read::<Fighter>().get(orc).unwrap().hp, 6);
+        let pos = world.read::<Position>().get(dagger).map(|p| (p.x, p.y));
+        assert_eq!(pos, Some((5, 1)));
+        let result = perform(&mut world, hero, Action::Fire { slot: 0, x: 5, y: 1 });
+        assert_eq!(result.outcome, Err(ActionError::NoSuchItem(0)));
+    }
+
+    #[test]
+    fn potions_heal_only_the_hurt() {
+        let mut world = level("#@#");
+        let hero = hero(&mut world, 1, 0);
+        let potion = spawn(&mut world, "Healing Potion", 1, 0).unwrap();
+        perform(&mut world, hero, Action::PickUp);
+
+        let result = perform(&mut world, hero, Action::Use { slot: 0 });
+        assert_eq!(result.outcome, Err(ActionError::AlreadyHealthy));
+
+        world.write::<Fighter>().get_mut(hero).unwrap().hp = 5;
+        assert!(perform(&mut world, hero, Action::Use { slot: 0 }).succeeded());
+        assert_eq!(world.read::<Fighter>().get(hero).unwrap().hp, 13);
+        assert!(world.read::<Inventory>().get(hero).unwrap().items.is_empty());
+        assert!(!world.is_alive(potion));
+    }
+
+    #[test]
+    fn eating_fills_you_up() {
+        let mut world = level("#@#");
+        let hero = hero(&mut world, 1, 0);
+        spawn(&mut world, "Ration", 1, 0).unwrap();
+        spawn(&mut world, "Ration", 1, 0).unwrap();
+        perform(&mut world, hero, Action::PickUp);
+        assert_eq!(perform(&mut world, hero, Action::Use { slot: 0 }).outcome,
+                   Err(ActionError::CantUse(String::from("Ration"))));
+
+        world.write::<Hunger>().insert(hero, Hunger { food: 50, max_food: 1000 });
+        let watcher = world.write_resource::<EventBus>().subscribe(&[EventKind::HungerChanged]);
+        assert!(perform(&mut world, hero, Action::Use { slot: 0 }).succeeded());
+        assert!(perform(&mut world, hero, Action::Use { slot: 0 }).succeeded());
+        assert_eq!(world.read::<Hunger>().get(hero).unwrap().food, 850);
+        assert_eq!(world.write_resource::<EventBus>().read(watcher), vec![
+            GameEvent::HungerChanged { entity: hero, state: HungerState::Normal },
+            GameEvent::HungerChanged { entity: hero, state: HungerState::Satiated },
+        ]);
+    }
+}
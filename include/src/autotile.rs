@@ -0,0 +1,168 @@
+use map::Map;
+use std::env;
+
+/// Which characters the terminal can be trusted to display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    Ascii,
+    Unicode,
+}
+
+impl Charset {
+    /// Guess from the locale environment variables, the same way the C library would.
+    /// The first of `LC_ALL`, `LC_CTYPE` and `LANG` which is set wins.
+    pub fn detect() -> Self {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"].iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        Charset::from_locale(&locale)
+    }
+
+    pub fn from_locale(locale: &str) -> Self {
+        let locale = locale.to_lowercase();
+        if locale.contains("utf-8") || locale.contains("utf8") {
+            Charset::Unicode
+        } else {
+            Charset::Ascii
+        }
+    }
+}
+
+// Bits for which neighbours are also visible walls.
+const NORTH: usize = 1;
+const EAST: usize = 2;
+const SOUTH: usize = 4;
+const WEST: usize = 8;
+
+/// Glyphs indexed by neighbour mask.
+const BOX_GLYPHS: [char; 16] = [
+    '■', '│', '─', '└', '│', '│', '┌', '├',
+    '─', '┘', '─', '┴', '┐', '┤', '┬', '┼',
+];
+const ASCII_GLYPHS: [char; 16] = [
+    '#', '|', '-', '+', '|', '|', '+', '+',
+    '-', '+', '-', '+', '+', '+', '+', '+',
+];
+
+/// Box drawing glyphs for every wall on a map, worked out from the neighbouring walls.
+/// This is relatively expensive, so it is meant to be computed once and kept around
+/// until the map changes (see `Map::revision`).
+pub struct AutoTiles {
+    width: i32,
+    height: i32,
+    revision: usize,
+    glyphs: Vec<Option<char>>,
+}
+
+impl AutoTiles {
+    pub fn compute(map: &Map, charset: Charset) -> Self {
+        let (width, height) = (map.width(), map.height());
+        let table = match charset {
+            Charset::Ascii => &ASCII_GLYPHS,
+            Charset::Unicode => &BOX_GLYPHS,
+        };
+
+        // Only walls which border something other than wall get drawn.
+        // Solid rock in between rooms stays blank.
+        let visible = |x: i32, y: i32| {
+            map.is_wall(x, y) && map.neighbours(x, y).any(|(nx, ny)| !map.is_wall(nx, ny))
+        };
+
+        let mut glyphs = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let glyph = if !map.is_wall(x, y) {
+                    None
+                } else if !visible(x, y) {
+                    Some(' ')
+                } else {
+                    let mut mask = 0;
+                    if visible(x, y - 1) { mask |= NORTH; }
+                    if visible(x + 1, y) { mask |= EAST; }
+                    if visible(x, y + 1) { mask |= SOUTH; }
+                    if visible(x - 1, y) { mask |= WEST; }
+                    Some(table[mask])
+                };
+                glyphs.push(glyph);
+            }
+        }
+
+        AutoTiles { width, height, revision: map.revision(), glyphs }
+    }
+
+    /// True if these glyphs were computed from the map as it is now.
+    pub fn is_current(&self, map: &Map) -> bool {
+        self.revision == map.revision()
+    }
+
+    /// The glyph for the wall at the given position, or `None` if there is no wall there.
+    pub fn glyph(&self, x: i32, y: i32) -> Option<char> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
+        }
+        self.glyphs[(y * self.width + x) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use autotile::{AutoTiles, Charset};
+    use map::{Map, Tile, TileKind};
+
+    fn room() -> Map {
+        // A 3x2 room in the middle of a 7x6 map.
+        let mut map = Map::new(7, 6);
+        for y in 2..4 {
+            for x in 2..5 {
+                map.set(x, y, Tile::from_kind(TileKind::Floor));
+            }
+        }
+        map
+    }
+
+    fn render(map: &Map, tiles: &AutoTiles) -> Vec<String> {
+        (0..map.height()).map(|y| {
+            (0..map.width())
+                .map(|x| tiles.glyph(x, y).unwrap_or('.'))
+                .collect()
+        }).collect()
+    }
+
+    #[test]
+    fn draws_box_around_room() {
+        let map = room();
+        let tiles = AutoTiles::compute(&map, Charset::Unicode);
+        assert_eq!(render(&map, &tiles), vec![
+            "       ",
+            " ┌───┐ ",
+            " │...│ ",
+            " │...│ ",
+            " └───┘ ",
+            "       ",
+        ]);
+
+        let tiles = AutoTiles::compute(&map, Charset::Ascii);
+        assert_eq!(render(&map, &tiles)[1], " +---+ ");
+    }
+
+    #[test]
+    fn recomputes_only_after_change() {
+        let mut map = room();
+        let tiles = AutoTiles::compute(&map, Charset::Unicode);
+        assert!(tiles.is_current(&map));
+        map.set(4, 1, Tile::from_kind(TileKind::Floor));
+        assert!(!tiles.is_current(&map));
+
+        let tiles = AutoTiles::compute(&map, Charset::Unicode);
+        assert_eq!(render(&map, &tiles)[1], " ┌─┘.│ ");
+    }
+
+    #[test]
+    fn detects_locale() {
+        assert_eq!(Charset::from_locale("en_AU.UTF-8"), Charset::Unicode);
+        assert_eq!(Charset::from_locale("C.utf8"), Charset::Unicode);
+        assert_eq!(Charset::from_locale("C"), Charset::Ascii);
+        assert_eq!(Charset::from_locale(""), Charset::Ascii);
+    }
+}
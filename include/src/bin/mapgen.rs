@@ -0,0 +1,163 @@
+//! Generate a map without starting the game, for tuning the generator.
+//!
+//!     cargo run --bin mapgen -- --seed 42 --corridor astar --stats
+
+extern crate rlr;
+extern crate env_logger;
+extern crate specs;
+extern crate rand;
+
+use std::env;
+use std::io::{self, Write};
+use std::process;
+
+use rlr::component::register_all;
+use rlr::corridor::CorridorStyle;
+use rlr::export::{entities_from_world, to_json, to_pbm, to_ppm, to_text};
+use rlr::map::Map;
+use rlr::map_utils::{make_map, seeded_rng, MapConfig, MapStats};
+use rlr::spawn::SpawnTable;
+use rlr::theme::Theme;
+use specs::World;
+
+const USAGE: &str = "Usage: mapgen [options]
+
+Generates a map and prints it to stdout. Statistics go to stderr.
+
+Options:
+    --seed N          Seed for the generator (random if not given)
+    --size WxH        Size of the map (default 80x40)
+    --depth N         Dungeon depth, which decides what spawns (default 1)
+    --corridor STYLE  lshaped, straight, drunken or astar (default lshaped)
+    --loops N         Extra corridors between nearby rooms (default 2)
+    --rooms N         Most rooms to try placing (default 30)
+    --room-size A-B   Smallest and largest room size (default 6-10)
+    --vaults N        Most prefab vaults to place (default 2)
+    --format FORMAT   text, json, ppm or pbm (default text)
+    --scale N         Pixels per tile for ppm (default 4)
+    --stats           Print statistics about the map
+    --help            Show this message";
+
+struct Options {
+    seed: usize,
+    width: usize,
+    height: usize,
+    config: MapConfig,
+    format: String,
+    scale: usize,
+    stats: bool,
+}
+
+fn parse_pair(value: &str, separator: char) -> Option<(usize, usize)> {
+    let mut parts = value.splitn(2, separator);
+    let a = parts.next()?.parse().ok()?;
+    let b = parts.next()?.parse().ok()?;
+    Some((a, b))
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        seed: rand::random(),
+        width: 80,
+        height: 40,
+        config: MapConfig::default(),
+        format: String::from("text"),
+        scale: 4,
+        stats: false,
+    };
+
+    while let Some(arg) = args.next() {
+        if arg == "--stats" {
+            options.stats = true;
+            continue;
+        }
+        if arg == "--help" {
+            return Err(String::new());
+        }
+        let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+        let bad_value = || format!("bad value for {}: {}", arg, value);
+        match arg.as_str() {
+            "--seed" => options.seed = value.parse().map_err(|_| bad_value())?,
+            "--size" => {
+                let (w, h) = parse_pair(&value, 'x').ok_or_else(bad_value)?;
+                options.width = w;
+                options.height = h;
+            },
+            "--depth" => options.config.depth = value.parse().map_err(|_| bad_value())?,
+            "--corridor" => options.config.corridor = match value.as_str() {
+                "lshaped" => CorridorStyle::LShaped,
+                "straight" => CorridorStyle::Straight,
+                "drunken" => CorridorStyle::Drunken,
+                "astar" => CorridorStyle::AStar,
+                _ => return Err(bad_value()),
+            },
+            "--loops" => options.config.extra_connections = value.parse().map_err(|_| bad_value())?,
+            "--rooms" => options.config.max_rooms = value.parse().map_err(|_| bad_value())?,
+            "--room-size" => {
+                let (min, max) = parse_pair(&value, '-').ok_or_else(bad_value)?;
+                options.config.room_min_size = min;
+                options.config.room_max_size = max;
+            },
+            "--vaults" => options.config.max_vaults = value.parse().map_err(|_| bad_value())?,
+            "--format" => match value.as_str() {
+                "text" | "json" | "ppm" | "pbm" => options.format = value.clone(),
+                _ => return Err(bad_value()),
+            },
+            "--scale" => options.scale = value.parse().map_err(|_| bad_value())?,
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn main() {
+    env_logger::init().unwrap();
+
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("mapgen: {}\n", message);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        },
+    };
+
+    let mut world = World::new();
+    register_all(&mut world);
+
+    let mut map = Map::new(options.width, options.height);
+    let layout = make_map(&mut map, &mut world, &options.config, &SpawnTable::default(),
+                          &mut seeded_rng(options.seed));
+    let entities = entities_from_world(&world);
+
+    let output = match options.format.as_str() {
+        "json" => to_json(&map, &entities).into_bytes(),
+        "ppm" => to_ppm(&map, &entities, options.scale),
+        "pbm" => to_pbm(&map).into_bytes(),
+        _ => {
+            let mut text = to_text(&map, &entities, &Theme::default());
+            text.push('\n');
+            text.into_bytes()
+        },
+    };
+    let stdout = io::stdout();
+    if let Err(e) = stdout.lock().write_all(&output) {
+        eprintln!("mapgen: {}", e);
+        process::exit(1);
+    }
+
+    if options.stats {
+        let stats = MapStats::measure(&map, &layout);
+        eprintln!("seed:            {}", options.seed);
+        eprintln!("rooms:           {}", stats.rooms);
+        eprintln!("vaults:          {}", layout.vaults.len());
+        eprintln!("walkable:        {:.1}%", stats.walkable_ratio * 100.0);
+        eprintln!("dead ends:       {}", stats.dead_ends);
+        match stats.stairs_distance {
+            Some(steps) => eprintln!("start to stairs: {} steps", steps),
+            None => eprintln!("start to stairs: unreachable"),
+        }
+    }
+}
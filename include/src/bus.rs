@@ -0,0 +1,216 @@
+use std::collections::VecDeque;
+
+use specs::Entity;
+
+use action::ActionResult;
+use event::Event;
+use component::HungerState;
+use status::EffectKind;
+
+/// Something which happened in the game, for any system which cares about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameEvent {
+    /// Something the player asked for.
+    Input(Event),
+    /// An actor tried to do something, and this is how it went.
+    Acted(ActionResult),
+    Moved { entity: Entity, from: (i32, i32), to: (i32, i32) },
+    /// `source` is whoever did it, or the target itself for things like poison.
+    Damaged { target: Entity, source: Entity, amount: i32 },
+    Died { entity: Entity },
+    PickedUp { entity: Entity, item: Entity },
+    EffectEnded { entity: Entity, effect: EffectKind },
+    /// Something was shot or thrown along `path`.
+    Fired { entity: Entity, path: Vec<(i32, i32)> },
+    /// Something got hungrier, or ate.
+    HungerChanged { entity: Entity, state: HungerState },
+    Talked { entity: Entity, target: Entity },
+}
+
+/// The kinds of `GameEvent`, for choosing what to subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Input,
+    Acted,
+    Moved,
+    Damaged,
+    Died,
+    PickedUp,
+    EffectEnded,
+    Fired,
+    HungerChanged,
+    Talked,
+}
+
+impl EventKind {
+    pub fn all() -> &'static [EventKind] {
+        use self::EventKind::*;
+        &[Input, Acted, Moved, Damaged, Died, PickedUp, EffectEnded, Fired, HungerChanged, Talked]
+    }
+}
+
+impl GameEvent {
+    pub fn kind(&self) -> EventKind {
+        match *self {
+            GameEvent::Input(_) => EventKind::Input,
+            GameEvent::Acted(_) => EventKind::Acted,
+            GameEvent::Moved { .. } => EventKind::Moved,
+            GameEvent::Damaged { .. } => EventKind::Damaged,
+            GameEvent::Died { .. } => EventKind::Died,
+            GameEvent::PickedUp { .. } => EventKind::PickedUp,
+            GameEvent::EffectEnded { .. } => EventKind::EffectEnded,
+            GameEvent::Fired { .. } => EventKind::Fired,
+            GameEvent::HungerChanged { .. } => EventKind::HungerChanged,
+            GameEvent::Talked { .. } => EventKind::Talked,
+        }
+    }
+}
+
+/// Handed out by `EventBus::subscribe`, and used to read events back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscriberId(usize);
+
+struct Subscriber {
+    kinds: Vec<EventKind>,
+    /// Sequence number of the next event this subscriber hasn't read.
+    next: usize,
+}
+
+/// A channel of `GameEvent`s. Each subscriber reads every event of the kinds it
+/// asked for, in order, no matter when it was emitted. Events are kept until
+/// everyone who wants them has read them, so a system running later in a tick
+/// sees what earlier ones emitted, and one which doesn't run every tick catches up.
+#[derive(Default)]
+pub struct EventBus {
+    events: VecDeque<GameEvent>,
+    /// Sequence number of `events[0]`.
+    first: usize,
+    subscribers: Vec<Option<Subscriber>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        EventBus::default()
+    }
+
+    /// Start listening for events of the given kinds. Only events emitted
+    /// from now on will be read.
+    pub fn subscribe(&mut self, kinds: &[EventKind]) -> SubscriberId {
+        let subscriber = Subscriber { kinds: kinds.to_vec(), next: self.first + self.events.len() };
+        self.subscribers.push(Some(subscriber));
+        SubscriberId(self.subscribers.len() - 1)
+    }
+
+    /// Stop listening. Events only this subscriber wanted can then be forgotten.
+    pub fn unsubscribe(&mut self, id: SubscriberId) {
+        if let Some(slot) = self.subscribers.get_mut(id.0) {
+            *slot = None;
+        }
+    }
+
+    fn wanted(&self, kind: EventKind) -> bool {
+        self.subscribers.iter().flatten().any(|sub| sub.kinds.contains(&kind))
+    }
+
+    /// Send an event to everyone subscribed to its kind. An event nobody is
+    /// subscribed to is still kept until the next `maintain`, which warns about it.
+    pub fn emit(&mut self, event: GameEvent) {
+        debug!("Emitted {:?}", event);
+        self.events.push_back(event);
+    }
+
+    /// Every event this subscriber hasn't read yet, oldest first.
+    pub fn read(&mut self, id: SubscriberId) -> Vec<GameEvent> {
+        let end = self.first + self.events.len();
+        let sub = match self.subscribers.get_mut(id.0) {
+            Some(&mut Some(ref mut sub)) => sub,
+            _ => return Vec::new(),
+        };
+        let unread = self.events.iter()
+            .skip(sub.next.saturating_sub(self.first))
+            .filter(|event| sub.kinds.contains(&event.kind()))
+            .cloned()
+            .collect();
+        sub.next = end;
+        unread
+    }
+
+    /// Forget events which every subscriber who wants them has read.
+    pub fn maintain(&mut self) {
+        let end = self.first + self.events.len();
+        let (first, events) = (self.first, &self.events);
+        // The oldest event anyone still has to read.
+        let oldest = self.subscribers.iter().flatten()
+            .filter_map(|sub| {
+                (sub.next.max(first)..end).find(|&n| sub.kinds.contains(&events[n - first].kind()))
+            })
+            .min()
+            .unwrap_or(end);
+        let forgotten: Vec<_> = self.events.drain(..oldest - self.first).collect();
+        for event in forgotten.iter().filter(|event| !self.wanted(event.kind())) {
+            warn!("Nobody was listening for {:?}", event);
+        }
+        self.first = oldest;
+    }
+
+    /// Events kept because someone hasn't read them yet.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::World;
+    use bus::{EventBus, EventKind, GameEvent};
+    use event::Event;
+
+    #[test]
+    fn subscribers_see_only_their_kinds_in_order() {
+        let mut world = World::new();
+        let (a, b) = (world.create_entity().build(), world.create_entity().build());
+        let mut bus = EventBus::new();
+        let input = bus.subscribe(&[EventKind::Input]);
+        let deaths = bus.subscribe(&[EventKind::Died, EventKind::Damaged]);
+
+        bus.emit(GameEvent::Input(Event::Wait));
+        bus.emit(GameEvent::Damaged { target: a, source: b, amount: 3 });
+        bus.emit(GameEvent::Died { entity: a });
+        // Nobody wants this, but it is kept until the next `maintain` anyway.
+        bus.emit(GameEvent::Moved { entity: b, from: (0, 0), to: (1, 0) });
+        assert_eq!(bus.len(), 4);
+
+        assert_eq!(bus.read(input), vec![GameEvent::Input(Event::Wait)]);
+        assert_eq!(bus.read(input), vec![]);
+        // Deaths hasn't read anything yet, so everything after the input is kept.
+        bus.maintain();
+        assert_eq!(bus.len(), 3);
+        assert_eq!(bus.read(deaths), vec![
+            GameEvent::Damaged { target: a, source: b, amount: 3 },
+            GameEvent::Died { entity: a },
+        ]);
+        bus.maintain();
+        assert!(bus.is_empty());
+    }
+
+    #[test]
+    fn late_subscribers_start_from_now_and_leaving_frees_events() {
+        let mut bus = EventBus::new();
+        let early = bus.subscribe(EventKind::all());
+        bus.emit(GameEvent::Input(Event::PickUp));
+        let late = bus.subscribe(EventKind::all());
+        bus.emit(GameEvent::Input(Event::Wait));
+
+        assert_eq!(bus.read(late), vec![GameEvent::Input(Event::Wait)]);
+        bus.maintain();
+        assert_eq!(bus.len(), 2);
+        bus.unsubscribe(early);
+        bus.maintain();
+        assert!(bus.is_empty());
+        assert_eq!(bus.read(early), vec![]);
+    }
+}
@@ -1,5 +1,9 @@
-use specs::{Component, VecStorage, NullStorage};
-use entity::Color;
+use std::collections::HashSet;
+
+use specs::{Component, VecStorage, NullStorage, Entity, World};
+use entity::{Color, Attributes};
+use faction::Faction;
+use status::StatusEffects;
 
 #[derive(Debug)]
 pub struct Position {
@@ -24,6 +28,7 @@ pub struct BaseEntity {
     pub glyph: char,
     pub fg: Color,
     pub bg: Color,
+    pub attrs: Attributes,
     pub blocks: bool,
     pub name: String,
 }
@@ -42,3 +47,156 @@ pub struct Blocking;
 impl Component for Blocking {
     type Storage = NullStorage<Self>;
 }
+
+/// Something which can be picked up.
+#[derive(Default)]
+pub struct Item;
+impl Component for Item {
+    type Storage = NullStorage<Self>;
+}
+
+/// Opens any locked tile whose `lock` matches `id`.
+#[derive(Debug)]
+pub struct Key {
+    pub id: u32,
+}
+impl Component for Key {
+    type Storage = VecStorage<Self>;
+}
+
+/// Items being carried. Carried items have no `Position`.
+#[derive(Debug, Default)]
+pub struct Inventory {
+    pub items: Vec<Entity>,
+}
+impl Component for Inventory {
+    type Storage = VecStorage<Self>;
+}
+
+/// Something which can fight, and be hurt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fighter {
+    pub max_hp: i32,
+    pub hp: i32,
+    pub power: i32,
+    pub defense: i32,
+}
+impl Fighter {
+    pub fn new(max_hp: i32, power: i32, defense: i32) -> Self {
+        Fighter { max_hp, hp: max_hp, power, defense }
+    }
+}
+impl Component for Fighter {
+    type Storage = VecStorage<Self>;
+}
+
+/// An item which restores hit points when used.
+#[derive(Debug)]
+pub struct Healing {
+    pub amount: i32,
+}
+impl Component for Healing {
+    type Storage = VecStorage<Self>;
+}
+
+/// What an entity can see, updated every turn by `VisibilitySystem`.
+#[derive(Debug, Default)]
+pub struct Viewshed {
+    pub range: i32,
+    pub visible: HashSet<(i32, i32)>,
+}
+impl Viewshed {
+    pub fn new(range: i32) -> Self {
+        Viewshed { range, visible: HashSet::new() }
+    }
+}
+impl Component for Viewshed {
+    type Storage = VecStorage<Self>;
+}
+
+/// An item which hits things from a distance. Thrown items fly themselves and
+/// land where they stop; anything else shoots `Ammo` carried alongside it.
+#[derive(Debug)]
+pub struct Ranged {
+    pub range: i32,
+    pub damage: i32,
+    pub thrown: bool,
+}
+impl Component for Ranged {
+    type Storage = VecStorage<Self>;
+}
+
+/// Arrows and the like, used up one at a time by ranged weapons.
+#[derive(Debug)]
+pub struct Ammo {
+    pub count: u32,
+}
+impl Component for Ammo {
+    type Storage = VecStorage<Self>;
+}
+
+/// How well fed something is, from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HungerState {
+    Satiated,
+    Normal,
+    Hungry,
+    Weak,
+    Starving,
+}
+
+/// Goes down by one every turn. At 0, the owner starts to starve.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunger {
+    pub food: i32,
+    pub max_food: i32,
+}
+impl Hunger {
+    pub fn new(max_food: i32) -> Self {
+        Hunger { food: max_food, max_food }
+    }
+
+    /// Every state but starving is a share of `max_food`, so the bands never overlap.
+    pub fn state(&self) -> HungerState {
+        match self.food {
+            f if f > self.max_food * 4 / 5 => HungerState::Satiated,
+            f if f > self.max_food * 3 / 10 => HungerState::Normal,
+            f if f > self.max_food / 10 => HungerState::Hungry,
+            f if f > 0 => HungerState::Weak,
+            _ => HungerState::Starving,
+        }
+    }
+}
+impl Component for Hunger {
+    type Storage = VecStorage<Self>;
+}
+
+/// An item which is eaten when used, restoring this much `Hunger`.
+#[derive(Debug)]
+pub struct Food {
+    pub amount: i32,
+}
+impl Component for Food {
+    type Storage = VecStorage<Self>;
+}
+
+/// Register every component with a world, so every world can hold anything.
+pub fn register_all(world: &mut World) {
+    world.register::<Position>();
+    world.register::<MoveDelta>();
+    world.register::<BaseEntity>();
+    world.register::<Puppeted>();
+    world.register::<Blocking>();
+    world.register::<Item>();
+    world.register::<Key>();
+    world.register::<Inventory>();
+    world.register::<Fighter>();
+    world.register::<Healing>();
+    world.register::<StatusEffects>();
+    world.register::<Viewshed>();
+    world.register::<Ranged>();
+    world.register::<Ammo>();
+    world.register::<Hunger>();
+    world.register::<Food>();
+    world.register::<Faction>();
+}
@@ -0,0 +1,264 @@
+extern crate rand;
+
+use corridor::rand::Rng;
+
+use std::cmp::{self, Reverse};
+use std::collections::BinaryHeap;
+
+use map::{Map, Tile, TileKind};
+use map_utils::{make_h_tunnel, make_v_tunnel, Rect};
+
+/// How corridors between rooms are carved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CorridorStyle {
+    /// Along one axis then the other, picking which goes first at random.
+    LShaped,
+    /// As close to a straight line as you can get walking orthogonally.
+    Straight,
+    /// A random walk which drifts towards the destination.
+    Drunken,
+    /// The cheapest path, going around other rooms where it can and
+    /// reusing existing corridors.
+    AStar,
+}
+
+/// Extra cost for carving through or right next to a room which isn't
+/// one of the ends of the corridor. High enough that `AStar` goes a long
+/// way round first, but it will still go through if it has to.
+const ROOM_PENALTY: u32 = 20;
+
+/// Carve a walkable path between two points.
+/// `rooms` are only used by `AStar`, which tries to keep out of them.
+pub fn carve_corridor<R: Rng>(map: &mut Map, from: (i32, i32), to: (i32, i32), style: CorridorStyle,
+                              rooms: &[Rect], rng: &mut R) {
+    match style {
+        CorridorStyle::LShaped => carve_l_shaped(map, from, to, rng),
+        CorridorStyle::Straight => carve_straight(map, from, to),
+        CorridorStyle::Drunken => carve_drunken(map, from, to, rng),
+        CorridorStyle::AStar => carve_a_star(map, from, to, rooms),
+    }
+}
+
+fn carve(map: &mut Map, x: i32, y: i32) {
+    if !map.is_walkable(x, y) {
+        map.set(x, y, Tile::from_kind(TileKind::Floor));
+    }
+}
+
+fn carve_l_shaped<R: Rng>(map: &mut Map, (x1, y1): (i32, i32), (x2, y2): (i32, i32), rng: &mut R) {
+    if rng.gen() {
+        make_h_tunnel(map, x1, x2, y1);
+        make_v_tunnel(map, y1, y2, x2);
+    } else {
+        make_v_tunnel(map, y1, y2, x1);
+        make_h_tunnel(map, x1, x2, y2);
+    }
+}
+
+fn carve_straight(map: &mut Map, from: (i32, i32), to: (i32, i32)) {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let (step_x, step_y) = (dx.signum(), dy.signum());
+    let (mut x, mut y) = from;
+    carve(map, x, y);
+    // Take whichever step keeps us closest to the line between the two points.
+    // The cross product measures how far off the line we are.
+    while (x, y) != to {
+        let off_line = |x: i32, y: i32| ((x - from.0) * dy - (y - from.1) * dx).abs();
+        if y == to.1 || (x != to.0 && off_line(x + step_x, y) <= off_line(x, y + step_y)) {
+            x += step_x;
+        } else {
+            y += step_y;
+        }
+        carve(map, x, y);
+    }
+}
+
+fn carve_drunken<R: Rng>(map: &mut Map, from: (i32, i32), to: (i32, i32), rng: &mut R) {
+    // Wander for a while, then give up and go straight there.
+    let max_steps = 4 * ((to.0 - from.0).abs() + (to.1 - from.1).abs()) + 20;
+    let (mut x, mut y) = from;
+    carve(map, x, y);
+    for _ in 0..max_steps {
+        if (x, y) == to {
+            return;
+        }
+        let (dx, dy) = if rng.gen_range(0, 10) < 6 {
+            // Stagger towards the destination...
+            if to.0 != x && (to.1 == y || rng.gen()) {
+                ((to.0 - x).signum(), 0)
+            } else {
+                (0, (to.1 - y).signum())
+            }
+        } else {
+            // ...or off in any direction.
+            [(1, 0), (-1, 0), (0, 1), (0, -1)][rng.gen_range(0, 4)]
+        };
+        // Keep off the outer edge of the map.
+        let (nx, ny) = (x + dx, y + dy);
+        if nx > 0 && ny > 0 && nx < map.width() - 1 && ny < map.height() - 1 {
+            x = nx;
+            y = ny;
+            carve(map, x, y);
+        }
+    }
+    carve_straight(map, (x, y), to);
+}
+
+fn carve_a_star(map: &mut Map, from: (i32, i32), to: (i32, i32), rooms: &[Rect]) {
+    let (width, height) = (map.width(), map.height());
+    if !map.in_bounds(from.0, from.1) || !map.in_bounds(to.0, to.1) {
+        carve_straight(map, from, to);
+        return;
+    }
+    let index = |(x, y): (i32, i32)| (y * width + x) as usize;
+
+    // What it costs to carve into each tile.
+    let mut cost: Vec<u32> = map.iter().map(|(_, _, tile)| if tile.walkable { 1 } else { 2 }).collect();
+    for room in rooms.iter().filter(|room| !room.contains(from.0, from.1) && !room.contains(to.0, to.1)) {
+        let ((x1, y1), (x2, y2)) = room.corners();
+        for y in cmp::max(y1 - 1, 0)..cmp::min(y2 + 1, height) {
+            for x in cmp::max(x1 - 1, 0)..cmp::min(x2 + 1, width) {
+                cost[index((x, y))] += ROOM_PENALTY;
+            }
+        }
+    }
+    let heuristic = |(x, y): (i32, i32)| ((x - to.0).abs() + (y - to.1).abs()) as u32;
+
+    let mut best = vec![u32::MAX; cost.len()];
+    let mut came_from = vec![None; cost.len()];
+    let mut open = BinaryHeap::new();
+    best[index(from)] = 0;
+    open.push(Reverse((heuristic(from), from)));
+
+    while let Some(Reverse((_, at))) = open.pop() {
+        if at == to {
+            break;
+        }
+        let so_far = best[index(at)];
+        for (nx, ny) in map.cardinal_neighbours(at.0, at.1) {
+            // The outer edge of the map stays solid.
+            let edge = nx == 0 || ny == 0 || nx == width - 1 || ny == height - 1;
+            if edge && (nx, ny) != to {
+                continue;
+            }
+            let i = index((nx, ny));
+            let total = so_far + cost[i];
+            if total < best[i] {
+                best[i] = total;
+                came_from[i] = Some(at);
+                open.push(Reverse((total + heuristic((nx, ny)), (nx, ny))));
+            }
+        }
+    }
+
+    if came_from[index(to)].is_none() && from != to {
+        // Couldn't get there without going along the edge of the map.
+        carve_straight(map, from, to);
+        return;
+    }
+    let mut at = to;
+    carve(map, at.0, at.1);
+    while let Some(prev) = came_from[index(at)] {
+        at = prev;
+        carve(map, at.0, at.1);
+    }
+}
+
+/// Pick pairs of rooms to join up to make loops. Rooms next to each other in
+/// `rooms` are assumed to be joined already, so are left alone. Only pairs whose
+/// centres are at most `max_distance` apart (in steps) are considered, and
+/// the closest pairs are more likely to be picked.
+pub fn pick_loops<R: Rng>(rooms: &[Rect], count: usize, max_distance: i32, rng: &mut R) -> Vec<(usize, usize)> {
+    let distance = |a: &Rect, b: &Rect| {
+        let ((ax, ay), (bx, by)) = (a.center(), b.center());
+        (ax - bx).abs() + (ay - by).abs()
+    };
+
+    let mut candidates = Vec::new();
+    for i in 0..rooms.len() {
+        for j in i + 2..rooms.len() {
+            let d = distance(&rooms[i], &rooms[j]);
+            if d <= max_distance {
+                candidates.push((d, i, j));
+            }
+        }
+    }
+    candidates.sort();
+
+    let mut picked = Vec::new();
+    while picked.len() < count && !candidates.is_empty() {
+        // Lean towards the front of the list by picking the better of two.
+        let a = rng.gen_range(0, candidates.len());
+        let b = rng.gen_range(0, candidates.len());
+        let (_, i, j) = candidates.remove(cmp::min(a, b));
+        picked.push((i, j));
+    }
+    picked
+}
+
+#[cfg(test)]
+mod tests {
+    use corridor::{carve_corridor, pick_loops, CorridorStyle};
+    use map::Map;
+    use map_utils::{make_room, reachable_from, seeded_rng, Rect};
+
+    /// Two rooms on either side of a third one.
+    fn three_rooms() -> (Map, Vec<Rect>) {
+        let mut map = Map::new(30, 15);
+        let rooms = vec![Rect::new(2, 5, 4, 4), Rect::new(12, 4, 6, 7), Rect::new(24, 5, 4, 4)];
+        for room in rooms.iter() {
+            make_room(&mut map, room);
+        }
+        (map, rooms)
+    }
+
+    #[test]
+    fn every_style_connects() {
+        for &style in [CorridorStyle::LShaped, CorridorStyle::Straight,
+                       CorridorStyle::Drunken, CorridorStyle::AStar].iter() {
+            for seed in 0..50 {
+                let (mut map, rooms) = three_rooms();
+                let (from, to) = ((3, 6), (25, 7));
+                carve_corridor(&mut map, from, to, style, &rooms, &mut seeded_rng(seed));
+                let reached = reachable_from(&map, from);
+                assert!(reached[(to.1 * map.width() + to.0) as usize], "{:?} with seed {}", style, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn a_star_goes_around_rooms() {
+        let (mut map, rooms) = three_rooms();
+        let mut middle_before = Vec::new();
+        for y in 3..12 {
+            for x in 11..19 {
+                middle_before.push(map.is_walkable(x, y));
+            }
+        }
+
+        carve_corridor(&mut map, (3, 6), (25, 7), CorridorStyle::AStar, &rooms, &mut seeded_rng(0));
+        let mut middle_after = Vec::new();
+        for y in 3..12 {
+            for x in 11..19 {
+                middle_after.push(map.is_walkable(x, y));
+            }
+        }
+        assert_eq!(middle_before, middle_after);
+    }
+
+    #[test]
+    fn straight_corridors_are_short() {
+        let mut map = Map::new(20, 20);
+        carve_corridor(&mut map, (2, 3), (12, 8), CorridorStyle::Straight, &[], &mut seeded_rng(0));
+        let carved = map.iter().filter(|&(_, _, tile)| tile.walkable).count();
+        assert_eq!(carved, 10 + 5 + 1);
+    }
+
+    #[test]
+    fn loops_skip_neighbours_and_far_rooms() {
+        let rooms = vec![Rect::new(0, 0, 2, 2), Rect::new(10, 0, 2, 2),
+                         Rect::new(0, 10, 2, 2), Rect::new(50, 50, 2, 2)];
+        let loops = pick_loops(&rooms, 5, 20, &mut seeded_rng(3));
+        assert_eq!(loops, vec![(0, 2)]);
+    }
+}
@@ -0,0 +1,257 @@
+extern crate pancurses;
+
+use std::collections::VecDeque;
+use std::thread;
+use std::time::Duration;
+
+use bus::GameEvent;
+use event::Event;
+use game::Game;
+use input_handlers::handle_keys;
+use replay::{Pace, Recording};
+use render_backend::{BufferBackend, PancursesBackend};
+use render_functions::Renderer;
+use autotile::Charset;
+use theme::Theme;
+
+/// Something which shows a `Game` and decides what happens next.
+pub trait Driver {
+    /// Show the game as it is now. Called before every event.
+    fn draw(&mut self, game: &Game);
+    /// The next thing to do, or `None` when there is no more input.
+    fn next_event(&mut self) -> Option<Event>;
+}
+
+/// Step the game with events from the driver until the player quits or dies,
+/// or the driver runs out of input.
+pub fn run<D: Driver>(game: &mut Game, driver: &mut D) {
+    while game.is_running() {
+        driver.draw(game);
+        match driver.next_event() {
+            Some(event) => game.step(event),
+            None => break,
+        }
+    }
+    // Show how it ended.
+    if game.player_died() {
+        driver.draw(game);
+    }
+}
+
+/// How long each step of a projectile's flight stays on screen.
+const PROJECTILE_FRAME_MS: u64 = 30;
+
+/// Start showing anything which was shot or thrown during the last step.
+fn launch_projectiles(renderer: &mut Renderer, game: &Game) {
+    for event in game.last_events() {
+        if let GameEvent::Fired { ref path, .. } = *event {
+            renderer.launch(path.clone());
+        }
+    }
+}
+
+/// Put whatever the player should be told about the last step on the status bar.
+fn show_messages(renderer: &mut Renderer, game: &Game) {
+    renderer.set_message(game.messages().join(" "));
+}
+
+/// The themes a driver can cycle through with `Event::NextTheme`.
+struct Themes {
+    themes: Vec<Theme>,
+    index: usize,
+}
+
+impl Themes {
+    fn new(themes: Vec<Theme>, renderer: &mut Renderer) -> Self {
+        let themes = if themes.is_empty() { Theme::builtin() } else { themes };
+        renderer.set_theme(themes[0].clone());
+        Themes { themes, index: 0 }
+    }
+
+    fn next(&mut self, renderer: &mut Renderer) {
+        self.index = (self.index + 1) % self.themes.len();
+        renderer.set_theme(self.themes[self.index].clone());
+    }
+}
+
+/// Plays the game in a terminal.
+pub struct CursesDriver {
+    backend: PancursesBackend,
+    renderer: Renderer,
+    themes: Themes,
+}
+
+impl CursesDriver {
+    pub fn new(backend: PancursesBackend, themes: Vec<Theme>) -> Self {
+        let mut renderer = Renderer::new();
+        let themes = Themes::new(themes, &mut renderer);
+        pancurses::noecho();
+        pancurses::curs_set(0);
+        CursesDriver { backend, renderer, themes }
+    }
+}
+
+impl CursesDriver {
+    /// Block until any key is pressed. Returns false if it was `q`.
+    pub fn wait_for_key(&self) -> bool {
+        self.backend.window().getch() != Some(pancurses::Input::Character('q'))
+    }
+}
+
+impl Driver for CursesDriver {
+    fn draw(&mut self, game: &Game) {
+        launch_projectiles(&mut self.renderer, game);
+        show_messages(&mut self.renderer, game);
+        self.renderer.render_all(&mut self.backend, game.world());
+        while self.renderer.is_animating() {
+            thread::sleep(Duration::from_millis(PROJECTILE_FRAME_MS));
+            self.renderer.render_all(&mut self.backend, game.world());
+        }
+    }
+
+    fn next_event(&mut self) -> Option<Event> {
+        // Keep reading until we get a key that means something.
+        loop {
+            let input = self.backend.window().getch()?;
+            if let Some(event) = handle_keys(input) {
+                if let Event::NextTheme = event {
+                    self.themes.next(&mut self.renderer);
+                }
+                return Some(event);
+            }
+        }
+    }
+}
+
+/// Wraps another driver, remembering every event it hands out.
+pub struct Recorder<D: Driver> {
+    inner: D,
+    recording: Recording,
+}
+
+impl<D: Driver> Recorder<D> {
+    /// `seed` should be the one the game was made with.
+    pub fn new(inner: D, seed: usize) -> Self {
+        Recorder { inner, recording: Recording::new(seed) }
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    pub fn into_recording(self) -> Recording {
+        self.recording
+    }
+}
+
+impl<D: Driver> Driver for Recorder<D> {
+    fn draw(&mut self, game: &Game) {
+        self.inner.draw(game);
+    }
+
+    fn next_event(&mut self) -> Option<Event> {
+        let event = self.inner.next_event()?;
+        self.recording.events.push(event.clone());
+        Some(event)
+    }
+}
+
+/// Plays a recording back in the terminal.
+pub struct ReplayDriver {
+    display: CursesDriver,
+    events: VecDeque<Event>,
+    pace: Pace,
+}
+
+impl ReplayDriver {
+    pub fn new(display: CursesDriver, recording: Recording, pace: Pace) -> Self {
+        ReplayDriver { display, events: recording.events.into_iter().collect(), pace }
+    }
+
+    /// Leave the last frame up until a key is pressed.
+    pub fn finish(&self) {
+        self.display.wait_for_key();
+    }
+}
+
+impl Driver for ReplayDriver {
+    fn draw(&mut self, game: &Game) {
+        self.display.draw(game);
+    }
+
+    fn next_event(&mut self) -> Option<Event> {
+        match self.pace {
+            Pace::Instant => {},
+            Pace::Delay(delay) => thread::sleep(delay),
+            Pace::Step => if !self.display.wait_for_key() {
+                return None;
+            },
+        }
+        let event = self.events.pop_front()?;
+        if let Event::NextTheme = event {
+            self.display.themes.next(&mut self.display.renderer);
+        }
+        Some(event)
+    }
+}
+
+/// Plays a script of events without a terminal, drawing into a `BufferBackend`
+/// so the screen can still be checked afterwards.
+pub struct HeadlessDriver {
+    events: VecDeque<Event>,
+    backend: BufferBackend,
+    renderer: Renderer,
+    themes: Themes,
+    frames: usize,
+}
+
+impl HeadlessDriver {
+    pub fn new<I: IntoIterator<Item = Event>>(events: I, width: i32, height: i32) -> Self {
+        // Don't let the locale of whoever runs the tests change what is drawn.
+        let mut renderer = Renderer::new();
+        renderer.set_charset(Charset::Ascii);
+        let themes = Themes::new(Theme::builtin(), &mut renderer);
+        HeadlessDriver {
+            events: events.into_iter().collect(),
+            backend: BufferBackend::new(width, height),
+            renderer,
+            themes,
+            frames: 0,
+        }
+    }
+
+    /// What the screen looked like after the last frame.
+    pub fn screen(&self) -> String {
+        self.backend.contents()
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Events from the script which haven't been played yet.
+    pub fn remaining(&self) -> usize {
+        self.events.len()
+    }
+}
+
+impl Driver for HeadlessDriver {
+    fn draw(&mut self, game: &Game) {
+        launch_projectiles(&mut self.renderer, game);
+        show_messages(&mut self.renderer, game);
+        self.renderer.render_all(&mut self.backend, game.world());
+        self.frames += 1;
+        while self.renderer.is_animating() {
+            self.renderer.render_all(&mut self.backend, game.world());
+            self.frames += 1;
+        }
+    }
+
+    fn next_event(&mut self) -> Option<Event> {
+        let event = self.events.pop_front()?;
+        if let Event::NextTheme = event {
+            self.themes.next(&mut self.renderer);
+        }
+        Some(event)
+    }
+}
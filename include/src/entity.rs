@@ -1,6 +1,10 @@
-#[derive(Hash, Eq, PartialEq, Clone, Debug)]
+use std::ops::BitOr;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::Error;
+
+#[derive(Hash, Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub enum Color {
-    Default = -1,
+    Default,
     Black,
     Red,
     Green,
@@ -9,6 +13,96 @@ pub enum Color {
     Magenta,
     Cyan,
     White,
+    /// Any other colour. Rendered as closely as the terminal allows, see `palette::Palette`.
+    Rgb(Rgb),
+}
+
+impl Color {
+    pub fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Color::Rgb(Rgb { r, g, b })
+    }
+}
+
+/// A 24-bit colour.
+#[derive(Hash, Eq, PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// Text attributes for a glyph. Combine them with `|`.
+#[derive(Hash, Eq, PartialEq, Clone, Copy, Debug, Default)]
+pub struct Attributes(u8);
+
+impl Attributes {
+    pub const NONE: Attributes = Attributes(0);
+    pub const BOLD: Attributes = Attributes(1);
+    pub const DIM: Attributes = Attributes(1 << 1);
+    pub const UNDERLINE: Attributes = Attributes(1 << 2);
+    pub const REVERSE: Attributes = Attributes(1 << 3);
+    pub const BLINK: Attributes = Attributes(1 << 4);
+
+    /// True if every attribute in `other` is also set in `self`.
+    pub fn contains(self, other: Attributes) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    const NAMES: [(&'static str, Attributes); 5] = [
+        ("bold", Attributes::BOLD),
+        ("dim", Attributes::DIM),
+        ("underline", Attributes::UNDERLINE),
+        ("reverse", Attributes::REVERSE),
+        ("blink", Attributes::BLINK),
+    ];
+
+    /// Look up a single attribute by its lowercase name, eg. "bold".
+    pub fn from_name(name: &str) -> Option<Attributes> {
+        Attributes::NAMES.iter()
+            .find(|&&(n, _)| n == name)
+            .map(|&(_, attr)| attr)
+    }
+
+    /// Names of every attribute which is set.
+    pub fn names(self) -> Vec<&'static str> {
+        Attributes::NAMES.iter()
+            .filter(|&&(_, attr)| self.contains(attr))
+            .map(|&(name, _)| name)
+            .collect()
+    }
+}
+
+/// Attributes are written out as a list of names, eg. `["bold", "blink"]`.
+impl Serialize for Attributes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.names().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Attributes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let names: Vec<String> = Vec::deserialize(deserializer)?;
+        let mut attrs = Attributes::NONE;
+        for name in names.iter() {
+            match Attributes::from_name(name) {
+                Some(attr) => attrs = attrs | attr,
+                None => return Err(D::Error::custom(format!("unknown attribute `{}`", name))),
+            }
+        }
+        Ok(attrs)
+    }
+}
+
+impl BitOr for Attributes {
+    type Output = Attributes;
+
+    fn bitor(self, other: Attributes) -> Attributes {
+        Attributes(self.0 | other.0)
+    }
 }
 
 // pub fn get_blocking_entities_at(entities: &Vec<Entity>, x: i32, y: i32) -> Vec<&Entity> {
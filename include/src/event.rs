@@ -7,10 +7,19 @@ pub enum Direction {
     Right
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Event {
     Movement((i32, i32)),
+    /// Close every open door next to the player.
+    CloseDoors,
+    /// Pick up whatever the player is standing on.
+    PickUp,
+    /// Use the item in this inventory slot.
+    Use(usize),
+    /// Shoot or throw the first ranged item carried at the nearest thing in range.
+    Fire,
+    /// Let a turn go by.
+    Wait,
+    NextTheme,
     Quit,
 }
-
-pub struct EventQueue(pub Vec<Event>);
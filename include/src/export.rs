@@ -0,0 +1,205 @@
+extern crate serde_json;
+
+use specs::{Join, World};
+
+use component::{BaseEntity, Position};
+use entity::{Color, Rgb};
+use map::{Map, TileKind};
+use palette::to_rgb;
+use theme::Theme;
+
+/// Everything an exporter needs to know about an entity on the map.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EntityExport {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub glyph: char,
+    pub fg: Color,
+    pub blocks: bool,
+}
+
+/// Every entity with a position, in a stable order. Where several share a tile,
+/// blocking ones come last so they get drawn on top.
+pub fn entities_from_world(world: &World) -> Vec<EntityExport> {
+    let positions = world.read::<Position>();
+    let bases = world.read::<BaseEntity>();
+    let mut entities: Vec<EntityExport> = (&positions, &bases).join()
+        .map(|(pos, base)| EntityExport {
+            name: base.name.clone(),
+            x: pos.x,
+            y: pos.y,
+            glyph: base.glyph,
+            fg: base.fg.clone(),
+            blocks: base.blocks,
+        })
+        .collect();
+    entities.sort_by(|a, b| (a.y, a.x, a.blocks, &a.name).cmp(&(b.y, b.x, b.blocks, &b.name)));
+    entities
+}
+
+/// The map as plain text, one line per row, using the glyphs from a theme.
+pub fn to_text(map: &Map, entities: &[EntityExport], theme: &Theme) -> String {
+    let mut rows: Vec<Vec<char>> = (0..map.height())
+        .map(|y| (0..map.width())
+             .map(|x| map.get(x, y).map_or(' ', |tile| theme.style(tile.kind).glyph))
+             .collect())
+        .collect();
+    for entity in entities.iter().filter(|e| map.in_bounds(e.x, e.y)) {
+        rows[entity.y as usize][entity.x as usize] = entity.glyph;
+    }
+    rows.into_iter()
+        .map(|row| row.into_iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct LockExport {
+    x: i32,
+    y: i32,
+    key: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct MapExport<'a> {
+    width: i32,
+    height: i32,
+    /// Row-major.
+    tiles: Vec<TileKind>,
+    locks: Vec<LockExport>,
+    entities: &'a [EntityExport],
+}
+
+/// The map as pretty printed JSON.
+pub fn to_json(map: &Map, entities: &[EntityExport]) -> String {
+    let export = MapExport {
+        width: map.width(),
+        height: map.height(),
+        tiles: map.iter().map(|(_, _, tile)| tile.kind).collect(),
+        locks: map.iter()
+            .filter_map(|(x, y, tile)| tile.lock.map(|key| LockExport { x, y, key }))
+            .collect(),
+        entities,
+    };
+    serde_json::to_string_pretty(&export).unwrap()
+}
+
+/// Colours for tiles in images. These don't come from the theme, since most
+/// of the terminal colours there are "whatever the terminal likes".
+fn tile_rgb(kind: TileKind) -> (u8, u8, u8) {
+    match kind {
+        TileKind::Floor => (96, 96, 96),
+        TileKind::Wall => (24, 24, 24),
+        TileKind::DoorClosed => (139, 90, 43),
+        TileKind::DoorOpen => (205, 133, 63),
+        TileKind::Water => (30, 60, 200),
+        TileKind::Lava => (220, 60, 0),
+        TileKind::Grass => (40, 140, 40),
+        TileKind::StairsDown | TileKind::StairsUp => (255, 255, 255),
+    }
+}
+
+/// The map as a binary PPM (P6) image, with each tile `scale` pixels square.
+/// Entities are drawn as a dot of their colour in the middle of their tile.
+pub fn to_ppm(map: &Map, entities: &[EntityExport], scale: usize) -> Vec<u8> {
+    let scale = scale.max(1);
+    let (width, height) = (map.width() as usize, map.height() as usize);
+    let mut pixels = vec![(0u8, 0u8, 0u8); width * scale * height * scale];
+    let mut fill = |x: usize, y: usize, size: usize, offset: usize, colour: (u8, u8, u8)| {
+        for py in 0..size {
+            for px in 0..size {
+                let (ix, iy) = (x * scale + offset + px, y * scale + offset + py);
+                pixels[iy * width * scale + ix] = colour;
+            }
+        }
+    };
+
+    for (x, y, tile) in map.iter() {
+        fill(x as usize, y as usize, scale, 0, tile_rgb(tile.kind));
+    }
+    let dot = (scale / 2).max(1);
+    for entity in entities.iter().filter(|e| map.in_bounds(e.x, e.y)) {
+        let Rgb { r, g, b } = to_rgb(&entity.fg).unwrap_or(Rgb { r: 255, g: 255, b: 255 });
+        fill(entity.x as usize, entity.y as usize, dot, (scale - dot) / 2, (r, g, b));
+    }
+
+    let mut image = format!("P6\n{} {}\n255\n", width * scale, height * scale).into_bytes();
+    for (r, g, b) in pixels {
+        image.extend_from_slice(&[r, g, b]);
+    }
+    image
+}
+
+/// The map as a plain PBM (P1) image, one pixel per tile: black where you can't
+/// walk, white where you can. Doors count as walkable.
+pub fn to_pbm(map: &Map) -> String {
+    let mut image = format!("P1\n{} {}\n", map.width(), map.height());
+    for y in 0..map.height() {
+        let row: Vec<&str> = (0..map.width())
+            .map(|x| match map.get(x, y) {
+                Some(tile) if tile.walkable || tile.is_door() => "0",
+                _ => "1",
+            })
+            .collect();
+        image.push_str(&row.join(" "));
+        image.push('\n');
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_json;
+
+    use specs::World;
+    use component::{BaseEntity, Position};
+    use entity::{Attributes, Color};
+    use export::{entities_from_world, to_json, to_pbm, to_ppm, to_text, EntityExport};
+    use map::{Map, Tile, TileKind};
+    use theme::Theme;
+
+    fn small_map() -> (Map, Vec<EntityExport>) {
+        let mut map = Map::new(4, 3);
+        map.set(1, 1, Tile::from_kind(TileKind::Floor));
+        map.set(2, 1, Tile::from_kind(TileKind::DoorClosed));
+        map.get_mut(2, 1).unwrap().lock = Some(7);
+
+        let mut world = World::new();
+        world.register::<Position>();
+        world.register::<BaseEntity>();
+        world.create_entity()
+            .with(Position { x: 1, y: 1 })
+            .with(BaseEntity { glyph: '@', fg: Color::Red, bg: Color::Default, attrs: Attributes::NONE,
+                               blocks: true, name: String::from("Player") })
+            .build();
+        let entities = entities_from_world(&world);
+        (map, entities)
+    }
+
+    #[test]
+    fn exports_text_and_json() {
+        let (map, entities) = small_map();
+        assert_eq!(to_text(&map, &entities, &Theme::default()), "####\n#@+#\n####");
+
+        let json: serde_json::Value = serde_json::from_str(&to_json(&map, &entities)).unwrap();
+        assert_eq!(json["width"], 4);
+        assert_eq!(json["tiles"][5], "Floor");
+        assert_eq!(json["locks"][0]["key"], 7);
+        assert_eq!(json["entities"][0]["name"], "Player");
+    }
+
+    #[test]
+    fn exports_images() {
+        let (map, entities) = small_map();
+        assert_eq!(to_pbm(&map), "P1\n4 3\n1 1 1 1\n1 0 0 1\n1 1 1 1\n");
+
+        let ppm = to_ppm(&map, &entities, 2);
+        let header = "P6\n8 6\n255\n";
+        assert!(ppm.starts_with(header.as_bytes()));
+        assert_eq!(ppm.len(), header.len() + 8 * 6 * 3);
+        // The top left corner of the player's tile is red.
+        let pixel = header.len() + (2 * 8 + 2) * 3;
+        assert_eq!(&ppm[pixel..pixel + 3], &[205, 0, 0]);
+    }
+}
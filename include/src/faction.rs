@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use specs::{Component, VecStorage};
+
+/// Which side an entity is on. Entities without one are hostile to everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Faction {
+    Player,
+    Monsters,
+    /// Odd folk who mind their own business.
+    Strangers,
+}
+impl Component for Faction {
+    type Storage = VecStorage<Self>;
+}
+
+/// How one faction feels about another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    /// Attacked when bumped into, and fair game for anything aimed.
+    Hostile,
+    /// Talked to when bumped into.
+    Neutral,
+    /// Swapped places with when bumped into.
+    Friendly,
+}
+
+/// How every faction feels about every other, kept as a resource.
+/// Feelings are mutual. Factions are friendly with themselves and neutral
+/// to anyone they haven't been told about.
+///
+/// Bumping into someone and `Action::FireAtNearest` go by these. Nothing
+/// else picks targets yet, since monsters don't act on their own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relationships {
+    relations: HashMap<(Faction, Faction), Relation>,
+}
+
+impl Default for Relationships {
+    fn default() -> Self {
+        let mut relationships = Relationships::empty();
+        relationships.set(Faction::Player, Faction::Monsters, Relation::Hostile);
+        relationships
+    }
+}
+
+impl Relationships {
+    /// Everyone is neutral to everyone else.
+    pub fn empty() -> Self {
+        Relationships { relations: HashMap::new() }
+    }
+
+    pub fn set(&mut self, a: Faction, b: Faction, relation: Relation) {
+        self.relations.insert((a, b), relation);
+        self.relations.insert((b, a), relation);
+    }
+
+    pub fn get(&self, a: Faction, b: Faction) -> Relation {
+        match self.relations.get(&(a, b)) {
+            Some(&relation) => relation,
+            None if a == b => Relation::Friendly,
+            None => Relation::Neutral,
+        }
+    }
+
+    /// How two entities with these factions get on.
+    pub fn between(&self, a: Option<&Faction>, b: Option<&Faction>) -> Relation {
+        match (a, b) {
+            (Some(&a), Some(&b)) => self.get(a, b),
+            _ => Relation::Hostile,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use faction::{Faction, Relation, Relationships};
+
+    #[test]
+    fn relations_are_mutual_with_sensible_defaults() {
+        let mut relationships = Relationships::default();
+        assert_eq!(relationships.get(Faction::Monsters, Faction::Player), Relation::Hostile);
+        assert_eq!(relationships.get(Faction::Strangers, Faction::Player), Relation::Neutral);
+        assert_eq!(relationships.get(Faction::Monsters, Faction::Monsters), Relation::Friendly);
+        assert_eq!(relationships.between(Some(&Faction::Player), None), Relation::Hostile);
+
+        relationships.set(Faction::Strangers, Faction::Player, Relation::Friendly);
+        assert_eq!(relationships.get(Faction::Player, Faction::Strangers), Relation::Friendly);
+        relationships.set(Faction::Monsters, Faction::Monsters, Relation::Hostile);
+        assert_eq!(relationships.get(Faction::Monsters, Faction::Monsters), Relation::Hostile);
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashSet;
+
+use map::Map;
+use spatial::SpatialIndex;
+
+/// Every tile on the straight line from `from` to `to`, both ends included,
+/// using Bresenham's algorithm.
+pub fn line(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let (mut x, mut y) = from;
+    let (dx, dy) = ((to.0 - x).abs(), -(to.1 - y).abs());
+    let (sx, sy) = ((to.0 - x).signum(), (to.1 - y).signum());
+    let mut err = dx + dy;
+    let mut points = Vec::with_capacity((dx - dy) as usize + 1);
+    loop {
+        points.push((x, y));
+        if (x, y) == to {
+            return points;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
+
+/// True if nothing opaque is between the two tiles. The tiles themselves
+/// don't count, so walls can be seen.
+pub fn clear_line(map: &Map, from: (i32, i32), to: (i32, i32)) -> bool {
+    let points = line(from, to);
+    if points.len() <= 2 {
+        return true;
+    }
+    points[1..points.len() - 1].iter()
+        .all(|&(x, y)| map.get(x, y).is_some_and(|tile| tile.transparent))
+}
+
+/// Where a shot goes. See `line_of_fire`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineOfFire {
+    /// Every tile the shot passes through, not including where it started.
+    pub path: Vec<(i32, i32)>,
+    /// The tile with something blocking in it, if the shot hit anything.
+    /// This is always the last tile of the path.
+    pub hit: Option<(i32, i32)>,
+}
+
+/// Follow a shot from `from` towards `to`, going no more than `range` steps.
+/// It stops before anything opaque, and at the first tile with a blocking entity.
+pub fn line_of_fire(map: &Map, index: &SpatialIndex, from: (i32, i32), to: (i32, i32), range: i32)
+    -> LineOfFire
+{
+    let mut shot = LineOfFire { path: Vec::new(), hit: None };
+    for (x, y) in line(from, to).into_iter().skip(1) {
+        let far = (x - from.0).abs().max((y - from.1).abs()) > range;
+        if far || !map.get(x, y).is_some_and(|tile| tile.transparent) {
+            break;
+        }
+        shot.path.push((x, y));
+        if index.is_blocked(x, y) {
+            shot.hit = Some((x, y));
+            break;
+        }
+    }
+    shot
+}
+
+/// The tiles which can be seen from `origin` within `range` steps, by
+/// tracing a line to each of them. Walls next to a tile which can be seen
+/// are seen too, since lines along a wall often clip the wall tiles before.
+pub fn field_of_view(map: &Map, origin: (i32, i32), range: i32) -> HashSet<(i32, i32)> {
+    let mut visible = HashSet::new();
+    if !map.in_bounds(origin.0, origin.1) {
+        return visible;
+    }
+    let in_range = (origin.1 - range..=origin.1 + range)
+        .flat_map(|y| (origin.0 - range..=origin.0 + range).map(move |x| (x, y)))
+        .filter(|&(x, y)| map.in_bounds(x, y));
+    let (transparent, opaque): (Vec<_>, Vec<_>) = in_range
+        .partition(|&(x, y)| map.get(x, y).is_some_and(|tile| tile.transparent));
+    visible.extend(transparent.into_iter().filter(|&to| clear_line(map, origin, to)));
+    let walls: Vec<_> = opaque.into_iter()
+        .filter(|&to| clear_line(map, origin, to) || map.neighbours(to.0, to.1).any(|n| visible.contains(&n)))
+        .collect();
+    visible.extend(walls);
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::World;
+    use fov::{field_of_view, line, line_of_fire};
+    use import::{parse_map, Legend};
+    use spatial::SpatialIndex;
+
+    #[test]
+    fn lines_include_both_ends() {
+        assert_eq!(line((0, 0), (3, 1)), vec![(0, 0), (1, 0), (2, 1), (3, 1)]);
+        assert_eq!(line((2, 2), (2, -1)), vec![(2, 2), (2, 1), (2, 0), (2, -1)]);
+        assert_eq!(line((1, 1), (1, 1)), vec![(1, 1)]);
+    }
+
+    #[test]
+    fn walls_hide_what_is_behind_them() {
+        let map = parse_map("\
+.....
+.@.#.
+.....", &Legend::default()).unwrap().map;
+        let seen = field_of_view(&map, (1, 1), 3);
+        assert!(seen.contains(&(3, 1)));
+        assert!(!seen.contains(&(4, 1)));
+        assert!(seen.contains(&(4, 0)));
+        assert_eq!(field_of_view(&map, (1, 1), 0).len(), 1);
+
+        // Every wall along a corridor can be seen from inside it.
+        let map = parse_map("#######\n.@.....\n#######", &Legend::default()).unwrap().map;
+        let seen = field_of_view(&map, (1, 1), 8);
+        assert!((0..7).all(|x| seen.contains(&(x, 0)) && seen.contains(&(x, 2))));
+    }
+
+    #[test]
+    fn shots_stop_at_walls_blockers_and_range() {
+        let map = parse_map("......\n.@..#.\n......", &Legend::default()).unwrap().map;
+        let mut world = World::new();
+        let mut index = SpatialIndex::new(map.width(), map.height());
+
+        let shot = line_of_fire(&map, &index, (1, 1), (5, 1), 10);
+        assert_eq!(shot.path, vec![(2, 1), (3, 1)]);
+        assert_eq!(shot.hit, None);
+        assert_eq!(line_of_fire(&map, &index, (1, 1), (5, 1), 1).path, vec![(2, 1)]);
+
+        // Only blocking things stop a shot.
+        index.insert(world.create_entity().build(), 2, 0, false);
+        index.insert(world.create_entity().build(), 4, 0, true);
+        let shot = line_of_fire(&map, &index, (0, 0), (5, 0), 10);
+        assert_eq!(shot.path, vec![(1, 0), (2, 0), (3, 0), (4, 0)]);
+        assert_eq!(shot.hit, Some((4, 0)));
+    }
+}
@@ -0,0 +1,126 @@
+use entity::{Attributes, Color};
+
+/// One cell of the screen: a glyph, its colours and text attributes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cell {
+    pub glyph: char,
+    pub fg: Color,
+    pub bg: Color,
+    pub attrs: Attributes,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell { glyph: ' ', fg: Color::Default, bg: Color::Default, attrs: Attributes::NONE }
+    }
+}
+
+/// A fixed size grid of cells, stored row-major.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Frame {
+    width: i32,
+    height: i32,
+    cells: Vec<Cell>,
+}
+
+impl Frame {
+    pub fn new(width: i32, height: i32) -> Self {
+        let (width, height) = (width.max(0), height.max(0));
+        Frame {
+            width,
+            height,
+            cells: vec![Cell::default(); (width * height) as usize],
+        }
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            None
+        } else {
+            Some((y * self.width + x) as usize)
+        }
+    }
+
+    /// The cell at the given position, or `None` if it is off the frame.
+    pub fn get(&self, x: i32, y: i32) -> Option<&Cell> {
+        self.index(x, y).map(|i| &self.cells[i])
+    }
+
+    /// Overwrite a cell. Writes outside the frame are ignored.
+    pub fn set(&mut self, x: i32, y: i32, cell: Cell) {
+        if let Some(i) = self.index(x, y) {
+            self.cells[i] = cell;
+        }
+    }
+
+    /// Reset every cell to a blank.
+    pub fn clear(&mut self) {
+        for cell in self.cells.iter_mut() {
+            *cell = Cell::default();
+        }
+    }
+
+    /// Every cell which differs between this frame and `previous`, in row-major order.
+    /// If the frames are different sizes, every cell of this frame is returned.
+    pub fn diff<'a>(&'a self, previous: &'a Frame) -> Vec<(i32, i32, &'a Cell)> {
+        let same_size = self.size() == previous.size();
+        let width = self.width;
+        self.cells.iter()
+            .enumerate()
+            .filter(|&(i, cell)| !same_size || previous.cells[i] != *cell)
+            .map(|(i, cell)| (i as i32 % width, i as i32 / width, cell))
+            .collect()
+    }
+}
+
+/// How much output a frame generated.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Number of cells sent to the backend.
+    pub cells_written: usize,
+    /// Estimated number of bytes a terminal would receive for those cells.
+    pub bytes_written: usize,
+}
+
+impl FrameStats {
+    /// Bytes for a `CSI row ; col H` cursor movement sequence.
+    pub fn cursor_move_bytes(x: i32, y: i32) -> usize {
+        // ESC [ row ; col H, where row and col are 1-based.
+        4 + (y + 1).to_string().len() + (x + 1).to_string().len()
+    }
+
+    /// Bytes for a `CSI fg ; bg ; attrs m` style change sequence.
+    pub fn style_change_bytes(attrs: Attributes) -> usize {
+        // ESC [ 0 ; 3x ; 4x m, plus ";n" for each attribute.
+        let set = [Attributes::BOLD, Attributes::DIM, Attributes::UNDERLINE,
+                   Attributes::REVERSE, Attributes::BLINK]
+            .iter()
+            .filter(|&&attr| attrs.contains(attr))
+            .count();
+        10 + 2 * set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frame::{Cell, Frame};
+
+    #[test]
+    fn diff_only_reports_changed_cells() {
+        let before = Frame::new(3, 2);
+        let mut after = before.clone();
+        after.set(2, 1, Cell { glyph: '@', ..Cell::default() });
+        after.set(7, 7, Cell { glyph: '!', ..Cell::default() });
+
+        let changes = after.diff(&before);
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].0, changes[0].1, changes[0].2.glyph), (2, 1, '@'));
+
+        // A resize means we don't know what is on screen, so redraw everything.
+        assert_eq!(after.diff(&Frame::new(1, 1)).len(), 6);
+    }
+}
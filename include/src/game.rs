@@ -0,0 +1,265 @@
+extern crate rand;
+
+use std::collections::HashSet;
+
+use game::rand::StdRng;
+
+use specs::{Entity, Join, RunNow, World};
+
+use action::{ActionResult, TURN_COST};
+use bus::{EventBus, EventKind, GameEvent, SubscriberId};
+use component::{register_all, Position, MoveDelta, BaseEntity, Puppeted, Blocking, Inventory, Fighter, Viewshed,
+                Hunger};
+use entity::{Color, Attributes};
+use event::Event;
+use faction::{Faction, Relationships};
+use import::ImportedMap;
+use map::Map;
+use map_utils::{make_map, seeded_rng, MapConfig};
+use spatial::SpatialIndex;
+use spawn::{spawn, SpawnTable};
+use status::StatusEffects;
+use system::{DeathSystem, EventSystem, HungerSystem, SpatialIndexSystem, StatusSystem, UpdatePos,
+             VisibilitySystem};
+
+/// The random numbers for everything that happens during play, kept apart from
+/// map generation so the same seed and input always play out the same way.
+pub struct GameRng(pub StdRng);
+
+/// The game itself, without any way of showing it or getting input.
+/// Something else (see `driver`) feeds it `Event`s, one at a time.
+pub struct Game {
+    world: World,
+    player: Entity,
+    running: bool,
+    /// Set when the player dies, which also stops the game.
+    player_died: bool,
+    turns: u64,
+    /// Total time the player's actions have taken. Every `TURN_COST` of it is one
+    /// turn for everything else.
+    time: u64,
+    event_system: EventSystem,
+    death_system: DeathSystem,
+    /// Everything that happens, so the front end can hear about it.
+    watcher: SubscriberId,
+    last_events: Vec<GameEvent>,
+}
+
+impl Game {
+    /// A fresh game on a generated map. The same seed always gives the same game.
+    pub fn new(seed: usize) -> Self {
+        let mut world = Game::empty_world();
+        let mut map = Map::new(32, 32);
+        let layout = make_map(&mut map, &mut world, &MapConfig::default(), &SpawnTable::default(),
+                              &mut seeded_rng(seed));
+        Game::place_stranger(&mut world, &map, layout.start);
+        Game::with_map(world, map, layout.start, seed)
+    }
+
+    /// Put the mysterious stranger on the first free tile next to where the player starts.
+    fn place_stranger(world: &mut World, map: &Map, (sx, sy): (i32, i32)) {
+        let occupied: HashSet<_> = world.read::<Position>().join().map(|pos| (pos.x, pos.y)).collect();
+        let spot = map.neighbours(sx, sy)
+            .find(|&(x, y)| map.is_walkable(x, y) && !occupied.contains(&(x, y)));
+        match spot {
+            Some((x, y)) => {
+                spawn(world, "Mysterious Glyph", x, y);
+            },
+            None => debug!("No room for the stranger next to {:?}", (sx, sy)),
+        }
+    }
+
+    /// A game on a map read from text. The player starts on the legend's start
+    /// character, or the top left corner if there isn't one. Play is seeded with 0.
+    pub fn from_imported(imported: ImportedMap) -> Self {
+        let mut world = Game::empty_world();
+        imported.spawn_into(&mut world);
+        let start = imported.start.unwrap_or((0, 0));
+        Game::with_map(world, imported.map, start, 0)
+    }
+
+    fn empty_world() -> World {
+        let mut world = World::new();
+        register_all(&mut world);
+        world
+    }
+
+    fn with_map(mut world: World, map: Map, (px, py): (i32, i32), seed: usize) -> Self {
+        let player = world.create_entity()
+            .with(Puppeted)
+            .with(Blocking)
+            .with(Inventory::default())
+            .with(Fighter::new(30, 5, 2))
+            .with(StatusEffects::new())
+            .with(Hunger::new(1000))
+            .with(Viewshed::new(8))
+            .with(Faction::Player)
+            .with(Position { x: px, y: py })
+            .with(MoveDelta { dx: 0, dy: 0 })
+            .with(BaseEntity {
+                    fg: Color::Red,
+                    bg: Color::Default,
+                    attrs: Attributes::NONE,
+                    glyph: '@',
+                    blocks: true,
+                    name: String::from("Player"),
+                })
+            .build();
+
+        let mut bus = EventBus::new();
+        let event_system = EventSystem::new(&mut bus);
+        let death_system = DeathSystem::new(&mut bus);
+        let watcher = bus.subscribe(EventKind::all());
+        world.add_resource(bus);
+        world.add_resource(SpatialIndex::new(map.width(), map.height()));
+        world.add_resource(map);
+        world.add_resource(GameRng(seeded_rng(seed)));
+        world.add_resource(Relationships::default());
+        VisibilitySystem.run_now(&world.res);
+
+        Game {
+            world,
+            player,
+            running: true,
+            player_died: false,
+            turns: 0,
+            time: 0,
+            event_system,
+            death_system,
+            watcher,
+            last_events: Vec::new(),
+        }
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    pub fn player(&self) -> Entity {
+        self.player
+    }
+
+    pub fn player_position(&self) -> Option<(i32, i32)> {
+        self.world.read::<Position>().get(self.player).map(|pos| (pos.x, pos.y))
+    }
+
+    /// False once the player has quit or died.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// True if the game ended because the player died.
+    pub fn player_died(&self) -> bool {
+        self.player_died
+    }
+
+    /// How many turns the player has spent. Actions which failed don't count.
+    pub fn turns(&self) -> u64 {
+        self.turns
+    }
+
+    /// Total time the player's actions have taken, in the same units as `TURN_COST`.
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Everything which happened during the last step, in order.
+    pub fn last_events(&self) -> &[GameEvent] {
+        &self.last_events
+    }
+
+    /// How each action from the last step went, so a front end can say why one failed.
+    pub fn last_results(&self) -> Vec<&ActionResult> {
+        self.last_events.iter()
+            .filter_map(|event| match *event {
+                GameEvent::Acted(ref result) => Some(result),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// What the player should be told about the last step: why their actions
+    /// failed, if they did, and who they talked to.
+    pub fn messages(&self) -> Vec<String> {
+        let names = self.world.read::<BaseEntity>();
+        self.last_events.iter()
+            .filter_map(|event| match *event {
+                GameEvent::Acted(ref result) if result.actor == self.player => {
+                    result.outcome.as_ref().err().map(|error| error.to_string())
+                },
+                GameEvent::Talked { entity, target } if entity == self.player => {
+                    let name = names.get(target).map_or("stranger", |base| &base.name);
+                    Some(format!("The {} has nothing to say.", name))
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Run one event through the world. Events which only matter to the
+    /// front end (like changing theme) are ignored, as is everything after `Quit`
+    /// or the player's death.
+    pub fn step(&mut self, event: Event) {
+        // Even a step which does nothing leaves nothing new to show.
+        self.last_events.clear();
+        if !self.running {
+            return;
+        }
+        match event {
+            Event::Quit => {
+                self.running = false;
+                return;
+            },
+            Event::NextTheme => return,
+            other => self.world.write_resource::<EventBus>().emit(GameEvent::Input(other)),
+        }
+
+        // Each system depends on the one before, so there is nothing to gain from
+        // a `Dispatcher` running them in parallel. (The one in our version of shred
+        // also trips the bounds checks in newer compilers.)
+        SpatialIndexSystem.run_now(&self.world.res);
+        self.event_system.run_now(&self.world.res);
+
+        // The rest of the world keeps up with however long the player took.
+        self.last_events = self.world.write_resource::<EventBus>().read(self.watcher);
+        let spent: u64 = self.last_results().iter()
+            .filter(|result| result.actor == self.player)
+            .map(|result| u64::from(result.cost))
+            .sum();
+        let turn = u64::from(TURN_COST);
+        for _ in self.time / turn..(self.time + spent) / turn {
+            StatusSystem.run_now(&self.world.res);
+            HungerSystem.run_now(&self.world.res);
+        }
+        self.time += spent;
+
+        self.death_system.run_now(&self.world.res);
+        UpdatePos.run_now(&self.world.res);
+        VisibilitySystem.run_now(&self.world.res);
+
+        // Everyone has had their chance to hear about this step, so only keep
+        // what a subscriber still hasn't read.
+        {
+            let mut bus = self.world.write_resource::<EventBus>();
+            let rest = bus.read(self.watcher);
+            self.last_events.extend(rest);
+            bus.maintain();
+        }
+
+        // Maintain dynamically added and removed entities in dispatch.
+        // This is what actually executes changes done by `LazyUpdate`.
+        self.world.maintain();
+        if spent > 0 {
+            self.turns += 1;
+        }
+        if self.last_events.contains(&GameEvent::Died { entity: self.player }) {
+            info!("The player has died.");
+            self.player_died = true;
+            self.running = false;
+        }
+    }
+}
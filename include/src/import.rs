@@ -0,0 +1,233 @@
+use specs::{Entity, World};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+use map::{Map, Tile, TileKind};
+use prefab::LegendEntry;
+use spawn::{spawn, SpawnList};
+
+/// What each character of an ASCII map means.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Legend {
+    entries: HashMap<char, LegendEntry>,
+    start: Option<char>,
+}
+
+impl Legend {
+    /// A legend which doesn't know any characters.
+    pub fn empty() -> Self {
+        Legend { entries: HashMap::new(), start: None }
+    }
+
+    /// `symbol` is a plain tile.
+    pub fn tile(self, symbol: char, tile: TileKind) -> Self {
+        self.entry(symbol, LegendEntry { tile, spawn: None, lock: None })
+    }
+
+    /// `symbol` is a tile with something spawned on it.
+    pub fn spawn(self, symbol: char, tile: TileKind, name: &str) -> Self {
+        self.entry(symbol, LegendEntry { tile, spawn: Some(String::from(name)), lock: None })
+    }
+
+    /// `symbol` is a tile locked with the given key id.
+    pub fn locked(self, symbol: char, tile: TileKind, key: u32) -> Self {
+        self.entry(symbol, LegendEntry { tile, spawn: None, lock: Some(key) })
+    }
+
+    /// `symbol` is the floor the player starts on.
+    pub fn start(mut self, symbol: char) -> Self {
+        self.entries.remove(&symbol);
+        self.start = Some(symbol);
+        self
+    }
+
+    fn entry(mut self, symbol: char, entry: LegendEntry) -> Self {
+        if self.start == Some(symbol) {
+            self.start = None;
+        }
+        self.entries.insert(symbol, entry);
+        self
+    }
+
+    pub fn get(&self, symbol: char) -> Option<&LegendEntry> {
+        self.entries.get(&symbol)
+    }
+}
+
+impl Default for Legend {
+    /// The same characters the ASCII theme draws with, plus `@` for the player
+    /// and a few letters for monsters and items.
+    fn default() -> Self {
+        Legend::empty()
+            .tile('.', TileKind::Floor)
+            .tile('#', TileKind::Wall)
+            .tile('+', TileKind::DoorClosed)
+            .tile('\'', TileKind::DoorOpen)
+            .tile('~', TileKind::Water)
+            .tile('^', TileKind::Lava)
+            .tile('"', TileKind::Grass)
+            .tile('>', TileKind::StairsDown)
+            .tile('<', TileKind::StairsUp)
+            .spawn('g', TileKind::Floor, "Goblin")
+            .spawn('o', TileKind::Floor, "Orc")
+            .spawn('T', TileKind::Floor, "Troll")
+            .spawn('!', TileKind::Floor, "Healing Potion")
+            .spawn('%', TileKind::Floor, "Ration")
+            .spawn(')', TileKind::Floor, "Dagger")
+            .spawn('}', TileKind::Floor, "Shortbow")
+            .spawn('/', TileKind::Floor, "Arrows")
+            .spawn('$', TileKind::Floor, "Mysterious Glyph")
+            .start('@')
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    /// There weren't any rows.
+    Empty,
+    /// A row was a different length to the first one. Lines are 1-based.
+    RaggedRow { line: usize, expected: usize, found: usize },
+    /// A character which isn't in the legend. Lines and columns are 1-based.
+    UnknownChar { line: usize, column: usize, found: char },
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ImportError::Io(ref e) => write!(f, "could not read map: {}", e),
+            ImportError::Empty => write!(f, "the map has no rows"),
+            ImportError::RaggedRow { line, expected, found } => write!(
+                f, "line {} is {} characters long, but the rows above it are {}", line, found, expected),
+            ImportError::UnknownChar { line, column, found } => write!(
+                f, "line {}, column {}: `{}` is not in the legend", line, column, found),
+        }
+    }
+}
+
+impl From<io::Error> for ImportError {
+    fn from(e: io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+/// A map read from text, and what should be put on it.
+pub struct ImportedMap {
+    pub map: Map,
+    pub spawns: SpawnList,
+    /// Where the player starts, if the legend has a start and the map used it.
+    pub start: Option<(i32, i32)>,
+}
+
+impl ImportedMap {
+    /// Create everything in `spawns`. Returns the entities in the same order.
+    pub fn spawn_into(&self, world: &mut World) -> Vec<Entity> {
+        self.spawns.iter()
+            .filter_map(|&(ref name, x, y)| spawn(world, name, x, y))
+            .collect()
+    }
+}
+
+/// Build a map from rows of text. Every row has to be the same length;
+/// blank lines at the end are ignored.
+pub fn parse_map(text: &str, legend: &Legend) -> Result<ImportedMap, ImportError> {
+    let mut rows: Vec<Vec<char>> = text.lines()
+        .map(|line| line.trim_end_matches('\r').chars().collect())
+        .collect();
+    while rows.last().is_some_and(|row| row.is_empty()) {
+        rows.pop();
+    }
+    if rows.is_empty() || rows[0].is_empty() {
+        return Err(ImportError::Empty);
+    }
+
+    let width = rows[0].len();
+    let mut map = Map::new(width, rows.len());
+    let mut spawns = Vec::new();
+    let mut start = None;
+    for (y, row) in rows.iter().enumerate() {
+        if row.len() != width {
+            return Err(ImportError::RaggedRow { line: y + 1, expected: width, found: row.len() });
+        }
+        for (x, &symbol) in row.iter().enumerate() {
+            let (x, y) = (x as i32, y as i32);
+            if legend.start == Some(symbol) {
+                map.set(x, y, Tile::from_kind(TileKind::Floor));
+                start = Some((x, y));
+                continue;
+            }
+            let entry = match legend.get(symbol) {
+                Some(entry) => entry,
+                None => return Err(ImportError::UnknownChar {
+                    line: y as usize + 1, column: x as usize + 1, found: symbol,
+                }),
+            };
+            map.set(x, y, Tile { lock: entry.lock, ..Tile::from_kind(entry.tile) });
+            if let Some(ref name) = entry.spawn {
+                spawns.push((name.clone(), x, y));
+            }
+        }
+    }
+
+    Ok(ImportedMap { map, spawns, start })
+}
+
+pub fn load_map<P: AsRef<Path>>(path: P, legend: &Legend) -> Result<ImportedMap, ImportError> {
+    let mut text = String::new();
+    fs::File::open(path)?.read_to_string(&mut text)?;
+    parse_map(&text, legend)
+}
+
+#[cfg(test)]
+mod tests {
+    use export::to_text;
+    use import::{parse_map, ImportError, Legend};
+    use map::{Map, Tile, TileKind};
+    use theme::Theme;
+
+    #[test]
+    fn reads_tiles_spawns_and_start() {
+        let legend = Legend::default().locked('L', TileKind::DoorClosed, 3);
+        let imported = parse_map("#####\n#@.o#\n##L##\n", &legend).unwrap();
+        let map = &imported.map;
+        assert_eq!((map.width(), map.height()), (5, 3));
+        assert_eq!(imported.start, Some((1, 1)));
+        assert!(map.is_walkable(1, 1));
+        assert_eq!(imported.spawns, vec![(String::from("Orc"), 3, 1)]);
+        assert_eq!(map.get(2, 2).unwrap().lock, Some(3));
+        assert!(map.is_wall(0, 0));
+    }
+
+    #[test]
+    fn reports_what_went_wrong() {
+        let legend = Legend::default();
+        match parse_map("###\n#.\n###", &legend) {
+            Err(ImportError::RaggedRow { line: 2, expected: 3, found: 2 }) => {},
+            other => panic!("expected a ragged row, got {:?}", other.err()),
+        }
+        let error = parse_map("###\n#.#\n#?#", &legend).err().unwrap();
+        assert_eq!(error.to_string(), "line 3, column 2: `?` is not in the legend");
+        assert!(matches!(parse_map("\n\n", &legend), Err(ImportError::Empty)));
+
+        // Characters can be taken out of the legend again.
+        let no_player = Legend::default().tile('@', TileKind::Wall);
+        assert_eq!(parse_map("@", &no_player).ok().unwrap().start, None);
+    }
+
+    #[test]
+    fn every_tile_survives_an_ascii_export() {
+        let mut map = Map::new(TileKind::all().len(), 1);
+        for (x, &kind) in TileKind::all().iter().enumerate() {
+            map.set(x as i32, 0, Tile::from_kind(kind));
+        }
+        let text = to_text(&map, &[], &Theme::builtin()[0]);
+        let imported = parse_map(&text, &Legend::default()).unwrap();
+        for (x, &kind) in TileKind::all().iter().enumerate() {
+            assert_eq!(imported.map.get(x as i32, 0).unwrap().kind, kind, "{:?}", text);
+        }
+    }
+}
@@ -8,6 +8,15 @@ pub fn handle_keys(user_input: pancurses::Input) -> Option<Event> {
         pancurses::Input::Character('j') => { info!("down"); Some(Event::Movement((0, 1))) },
         pancurses::Input::Character('k') => { info!("up"); Some(Event::Movement((0, -1))) },
         pancurses::Input::Character('l') => { info!("right"); Some(Event::Movement((1, 0))) },
+        pancurses::Input::Character('c') => { info!("close"); Some(Event::CloseDoors) },
+        pancurses::Input::Character('g') => { info!("pick up"); Some(Event::PickUp) },
+        pancurses::Input::Character(c @ '1'..='9') => {
+            info!("use");
+            Some(Event::Use(c as usize - '1' as usize))
+        },
+        pancurses::Input::Character('f') => { info!("fire"); Some(Event::Fire) },
+        pancurses::Input::Character('.') => { info!("wait"); Some(Event::Wait) },
+        pancurses::Input::Character('t') => { info!("next theme"); Some(Event::NextTheme) },
         pancurses::Input::Character('q') => { info!("Quitting!"); Some(Event::Quit) },
         _ => { None }
     }
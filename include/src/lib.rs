@@ -3,16 +3,42 @@
 #[macro_use]
 extern crate log;
 extern crate specs;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde;
+extern crate serde_json;
 
 // Define the modules this library exports.
 pub mod entity;
 pub mod event;
 pub mod input_handlers;
 pub mod render_functions;
+pub mod render_backend;
+pub mod frame;
+pub mod palette;
+pub mod theme;
+pub mod autotile;
 pub mod map;
 pub mod map_utils;
+pub mod corridor;
+pub mod spawn;
+pub mod export;
+pub mod import;
+pub mod prefab;
 pub mod game_state;
+pub mod game;
+pub mod driver;
+pub mod replay;
+pub mod action;
+pub mod bus;
+pub mod status;
+pub mod fov;
+pub mod faction;
 
 // ECS
 pub mod component;
 pub mod system;
+pub mod spatial;
+
+#[cfg(test)]
+mod testing;
@@ -3,16 +3,46 @@ extern crate pancurses;
 #[macro_use]
 extern crate log;
 extern crate env_logger;
-extern crate specs;
+extern crate rand;
 
-use rlr::event::{Event, EventQueue};
-use rlr::entity::{Color};
-use rlr::map::Map;
-// use rlr::game_state::GameState;
+use std::process;
+use std::time::Duration;
 
-use rlr::component::{Position, MoveDelta, BaseEntity, Puppeted, Blocking};
-use specs::World;
-use specs::DispatcherBuilder;
+use rlr::driver::{run, CursesDriver, Recorder, ReplayDriver};
+use rlr::game::Game;
+use rlr::render_backend::PancursesBackend;
+use rlr::replay::{Pace, Recording};
+use rlr::theme::Theme;
+
+const USAGE: &str = "Usage: rlr [--record FILE] [--replay FILE [--delay MS | --step]]
+
+    --record FILE   Save the seed and every key pressed to FILE
+    --replay FILE   Play back a recording made with --record
+    --delay MS      When replaying, wait this long between moves (default 100)
+    --step          When replaying, wait for a key press between moves (q stops)";
+
+struct Options {
+    record: Option<String>,
+    replay: Option<String>,
+    pace: Pace,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options { record: None, replay: None, pace: Pace::Delay(Duration::from_millis(100)) };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => options.record = Some(args.next().ok_or("--record needs a file")?),
+            "--replay" => options.replay = Some(args.next().ok_or("--replay needs a file")?),
+            "--delay" => {
+                let ms = args.next().and_then(|ms| ms.parse().ok()).ok_or("--delay needs a number")?;
+                options.pace = Pace::Delay(Duration::from_millis(ms));
+            },
+            "--step" => options.pace = Pace::Step,
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    Ok(options)
+}
 
 fn main() {
     // Initialize the logger in the main executable.
@@ -21,140 +51,61 @@ fn main() {
     env_logger::init().unwrap();
     info!("Starting RLR.");
 
-    let win = pancurses::initscr();
-
-    // Specs stuff
-    let mut world = World::new();
-    world.register::<Position>();
-    world.register::<MoveDelta>();
-    world.register::<BaseEntity>();
-    world.register::<Puppeted>();
-    world.register::<Blocking>();
-
-    let mut running = true;
-
-    let mut map = Map::new(32, 32);
-    let mut mobs = Vec::new();
-
-    let (px, py) = rlr::map_utils::make_map(&mut map, &mut world);
-
-    let player = world.create_entity()
-        .with(Puppeted)
-        .with(Blocking)
-        .with(Position { x: px as i32, y: py as i32 })
-        .with(MoveDelta { dx: 0, dy: 0 })
-        .with(BaseEntity {
-                fg: Color::Red,
-                bg: Color::Default,
-                glyph: '@',
-                blocks: true,
-                name: String::from("Player"),
-            })
-        .build();
-
-    let npc = world.create_entity()
-        .with(Position { x: 5, y: 5 })
-        .with(BaseEntity {
-                fg: Color::Blue,
-                bg: Color::Default,
-                glyph: '$',
-                blocks: true,
-                name: String::from("Mysterious Glyph"),
-            })
-        .build();
-
-    let mut entities = vec![player, npc];
-    entities.append(&mut mobs);
-
-    let mut renderer = rlr::render_functions::Renderer::new();
-    rlr::render_functions::Renderer::static_init();
-
-    // let mut game_state = GameState::PlayerTurn;
-
-    // SPECS: Systems + Dispatcher
-    let update_pos = rlr::system::UpdatePos;
-    // let mut render_system = rlr::system::RenderSystem;
-    let event_system = rlr::system::EventSystem;
-
-    let mut dispatcher = DispatcherBuilder::new()
-        .add(event_system, "event_system", &[])
-        .add(update_pos, "update_pos", &["event_system"])
-        // .add_thread_local(render_system)
-        .build();
-
-    world.add_resource(EventQueue(Vec::new()));
-    world.add_resource(map);
-
-    pancurses::noecho();
-    pancurses::curs_set(0);
-
-    while running {
-        renderer.render_all(&win, &world);
-
-        // Push the input into the world as a resource.
-        let input = win.getch();
-
-        if let Some(x) = input {
-            if let Some(event) = rlr::input_handlers::handle_keys(x) {
-                match event {
-                    // Some system events we need to handle here
-                    Event::Quit => { running = false; },
-
-                    // But almost everything else can be handled from the ECS
-                    // Just chuck it on the event queue ;D
-                    other => {
-                        let mut x = world.write_resource::<EventQueue>();
-                        (*x).0.push(other);
-                    },
-
-                    // Event::Movement((dx, dy)) => {
-                    //     if let GameState::PlayerTurn = game_state {
-                    //         // let pos = {
-                    //         //     let player = &entities[0];
-                    //         //     (player.x + dx, player.y + dy)
-                    //         // };
-                    //         // if map.data[pos.1 as usize][pos.0 as usize].walkable {
-                    //         //     // if rlr::entity::get_blocking_entities_at(&entities, pos.0, pos.1).len() > 0 {
-                    //         //     //     info!("Punt!");
-                    //         //     // }
-                    //         //     // else {
-                    //         //     //     let player = &mut entities[0];
-                    //         //     //     player.mov(dx, dy);
-                    //         //     // }
-                    //         // }
-                    //         // game_state = GameState::AITurn;
-                    //     }
-                    // },
-
-                }
-            }
+    let options = parse_args(std::env::args().skip(1)).unwrap_or_else(|message| {
+        eprintln!("rlr: {}\n\n{}", message, USAGE);
+        process::exit(2);
+    });
+
+    // Load the recording before touching the terminal, so errors are readable.
+    let replay = options.replay.as_ref().map(|path| {
+        Recording::load(path).unwrap_or_else(|e| {
+            eprintln!("rlr: {}: {}", path, e);
+            process::exit(1);
+        })
+    });
+
+    // Set RLR_SEED to play the same map again.
+    let seed = match replay {
+        Some(ref recording) => recording.seed,
+        None => std::env::var("RLR_SEED").ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_else(rand::random::<usize>),
+    };
+    info!("Map seed: {}", seed);
+    let mut game = Game::new(seed);
+
+    // Use the built in themes, plus one from a file if we've been given one.
+    let mut themes = Theme::builtin();
+    if let Ok(path) = std::env::var("RLR_THEME") {
+        match Theme::load(&path) {
+            Ok(theme) => themes.insert(0, theme),
+            Err(e) => error!("Ignoring theme {}: {}", path, e),
         }
+    }
 
-        // if let GameState::AITurn = game_state {
-        //     // for ent in entities.iter() {
-        //     //     if ent.name != "Player" {
-        //     //         info!("The {} ponders the meaning of its existence.", ent.name);
-        //     //     }
-        //     // }
-        //     game_state = GameState::PlayerTurn;
-        // }
-
-        dispatcher.dispatch(&world.res);
-
-        // We're all done with the events, so let's clear them out
-        {
-            let mut x = world.write_resource::<EventQueue>();
-            (*x).0 = Vec::new();
+    // `initscr` sets the locale from the environment. With the wide build of
+    // curses that lets unicode glyphs through instead of `M-b` escapes.
+    let driver = CursesDriver::new(PancursesBackend::new(pancurses::initscr()), themes);
+    let mut saved = Ok(());
+    if let Some(recording) = replay {
+        let mut driver = ReplayDriver::new(driver, recording, options.pace);
+        run(&mut game, &mut driver);
+        driver.finish();
+    } else {
+        let mut driver = Recorder::new(driver, seed);
+        run(&mut game, &mut driver);
+        if let Some(ref path) = options.record {
+            saved = driver.recording().save(path);
         }
-
-        // Maintain dynamically added and removed entities in dispatch.
-        // This is what actually executes changes done by `LazyUpdate`.
-        world.maintain();
-
-        // renderer.clear_entity(&win, &entities[0]);
-
-
     }
 
     pancurses::endwin();
+    if game.player_died() {
+        println!("You died after {} turns.", game.turns());
+    }
+
+    if let Err(e) = saved {
+        eprintln!("rlr: couldn't save the recording: {}", e);
+        process::exit(1);
+    }
 }
@@ -1,47 +1,349 @@
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+use entity::Attributes;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// What a tile is made of. Decides how it looks (see `theme::Theme`)
+/// and what it does by default.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TileKind {
+    Floor,
+    #[default]
+    Wall,
+    DoorClosed,
+    DoorOpen,
+    Water,
+    Lava,
+    Grass,
+    StairsDown,
+    StairsUp,
+}
+
+impl TileKind {
+    pub fn all() -> &'static [TileKind] {
+        &[TileKind::Floor, TileKind::Wall, TileKind::DoorClosed, TileKind::DoorOpen, TileKind::Water,
+          TileKind::Lava, TileKind::Grass, TileKind::StairsDown, TileKind::StairsUp]
+    }
+
+    /// Look a kind up by its name, eg. "Floor".
+    pub fn from_name(name: &str) -> Option<TileKind> {
+        TileKind::all().iter()
+            .find(|kind| format!("{:?}", kind) == name)
+            .cloned()
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 pub struct Tile {
+    pub kind: TileKind,
     pub transparent: bool,
     pub walkable: bool,
+    pub attrs: Attributes,
+    /// If set, this tile is locked and only opens for the `Key` with this id.
+    pub lock: Option<u32>,
 }
 impl Tile {
     pub fn new() -> Self {
-        Tile{ transparent: false, walkable: false }
+        Tile::from_kind(TileKind::Wall)
+    }
+
+    /// A tile of the given kind, with the usual walkability and transparency for that kind.
+    pub fn from_kind(kind: TileKind) -> Self {
+        let (walkable, transparent) = match kind {
+            TileKind::Floor | TileKind::Grass | TileKind::StairsDown | TileKind::StairsUp
+                | TileKind::DoorOpen => (true, true),
+            TileKind::Wall | TileKind::DoorClosed => (false, false),
+            TileKind::Water | TileKind::Lava => (false, true),
+        };
+        Tile { kind, transparent, walkable, attrs: Attributes::NONE, lock: None }
     }
+
+    pub fn is_door(&self) -> bool {
+        self.kind == TileKind::DoorClosed || self.kind == TileKind::DoorOpen
+    }
+}
+
+/// Every change to any map gets a new revision number from here,
+/// so revisions are never reused, even between different maps.
+static NEXT_REVISION: AtomicUsize = AtomicUsize::new(0);
+
+fn next_revision() -> usize {
+    NEXT_REVISION.fetch_add(1, Ordering::Relaxed)
 }
 
+/// A rectangular grid of tiles, stored row-major.
+/// Every accessor is bounds checked, so any coordinate is safe to ask about.
+#[derive(Clone)]
 pub struct Map {
-    pub data: Vec<Vec<Tile>>
+    width: i32,
+    height: i32,
+    tiles: Vec<Tile>,
+    revision: usize,
 }
 
+/// Offsets to the eight surrounding tiles, starting north and going clockwise.
+const NEIGHBOURS: [(i32, i32); 8] = [
+    (0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1),
+];
+
+/// Offsets to the four orthogonally adjacent tiles, starting north and going clockwise.
+const CARDINAL_NEIGHBOURS: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
 impl Map {
+    /// A map filled with walls. Sizes too big to address with an `i32` are clamped.
     pub fn new(width: usize, height: usize) -> Self {
-        // TODO investigate whether I should switch to a dynamically sized array instead of a vec
-        // https://stackoverflow.com/questions/13212212/creating-two-dimensional-arrays-in-rust
+        let max = i32::MAX as usize;
+        let (width, height) = (width.min(max), height.min(max));
+        Map {
+            width: width as i32,
+            height: height as i32,
+            tiles: vec![Tile::new(); width * height],
+            revision: next_revision(),
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn in_bounds(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.width && y < self.height
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if self.in_bounds(x, y) {
+            Some(y as usize * self.width as usize + x as usize)
+        } else {
+            None
+        }
+    }
+
+    /// The tile at the given position, or `None` if it is off the map.
+    pub fn get(&self, x: i32, y: i32) -> Option<&Tile> {
+        self.index(x, y).map(move |i| &self.tiles[i])
+    }
+
+    /// The tile at the given position, or `None` if it is off the map.
+    /// Changes made through this don't touch the revision, so use `set` for
+    /// anything derived data needs to notice.
+    pub fn get_mut(&mut self, x: i32, y: i32) -> Option<&mut Tile> {
+        self.index(x, y).map(move |i| &mut self.tiles[i])
+    }
+
+    /// Replace the tile at the given position, moving on to a new revision if it changed.
+    /// Returns false (and does nothing) if the position is off the map.
+    pub fn set(&mut self, x: i32, y: i32, tile: Tile) -> bool {
+        match self.index(x, y) {
+            Some(i) => {
+                if self.tiles[i] != tile {
+                    self.tiles[i] = tile;
+                    self.revision = next_revision();
+                }
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Every tile along with its position, in row-major order.
+    pub fn iter(&self) -> impl Iterator<Item = (i32, i32, &Tile)> {
+        let width = self.width;
+        self.tiles.iter()
+            .enumerate()
+            .map(move |(i, tile)| (i as i32 % width, i as i32 / width, tile))
+    }
+
+    /// Positions of the (up to eight) surrounding tiles which are on the map.
+    pub fn neighbours(&self, x: i32, y: i32) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.offsets(x, y, &NEIGHBOURS)
+    }
+
+    /// Positions of the (up to four) orthogonally adjacent tiles which are on the map.
+    pub fn cardinal_neighbours(&self, x: i32, y: i32) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.offsets(x, y, &CARDINAL_NEIGHBOURS)
+    }
+
+    fn offsets(&self, x: i32, y: i32, offsets: &'static [(i32, i32)])
+               -> impl Iterator<Item = (i32, i32)> + '_ {
+        offsets.iter()
+            .filter_map(move |&(dx, dy)| {
+                Some((x.checked_add(dx)?, y.checked_add(dy)?))
+            })
+            .filter(move |&(nx, ny)| self.in_bounds(nx, ny))
+    }
+
+    /// Changes whenever the map is changed, so derived data
+    /// (like `autotile::AutoTiles`) knows when to recompute.
+    pub fn revision(&self) -> usize {
+        self.revision
+    }
 
-        let base = vec![Tile::new(); width];
-        let grid = vec![base.clone(); height];
+    /// True if the tile is a wall. Anything off the edge of the map counts as wall.
+    pub fn is_wall(&self, x: i32, y: i32) -> bool {
+        self.get(x, y).is_none_or(|tile| tile.kind == TileKind::Wall)
+    }
 
-        Map{data: grid}
+    /// True if the tile can be walked on. Anything off the edge of the map can't be.
+    pub fn is_walkable(&self, x: i32, y: i32) -> bool {
+        self.get(x, y).is_some_and(|tile| tile.walkable)
     }
-    pub fn at(self: &Self, x: i32, y: i32) -> &Tile {
-        // Just cast to usize for now - implementation may change.
-        & self.data[y as usize][x as usize]
+
+    /// Swap a door tile between open and closed, keeping any attributes and lock.
+    /// Returns false if there is no door there, or it is already in that state.
+    fn set_door(&mut self, x: i32, y: i32, kind: TileKind) -> bool {
+        let tile = match self.get(x, y) {
+            Some(tile) if tile.is_door() && tile.kind != kind => *tile,
+            _ => return false,
+        };
+        self.set(x, y, Tile { attrs: tile.attrs, lock: tile.lock, ..Tile::from_kind(kind) })
     }
-    pub fn at_mut(self: &mut Self, x: i32, y: i32) -> &mut Tile {
-        // Just cast to usize for now - implementation may change.
-        &mut self.data[y as usize][x as usize]
+
+    /// Open the door at the given position. Locked doors stay shut.
+    pub fn open_door(&mut self, x: i32, y: i32) -> bool {
+        if self.get(x, y).is_some_and(|tile| tile.lock.is_some()) {
+            return false;
+        }
+        self.set_door(x, y, TileKind::DoorOpen)
+    }
+
+    /// Close the door at the given position, which blocks movement and sight again.
+    pub fn close_door(&mut self, x: i32, y: i32) -> bool {
+        self.set_door(x, y, TileKind::DoorClosed)
+    }
+
+    /// Lock the tile at the given position, so it only opens with `key`.
+    /// Returns false if the position is off the map.
+    pub fn lock(&mut self, x: i32, y: i32, key: u32) -> bool {
+        match self.get(x, y) {
+            Some(&tile) => self.set(x, y, Tile { lock: Some(key), ..tile }),
+            None => false,
+        }
+    }
+
+    /// Remove the lock from a tile if `key` fits it.
+    pub fn unlock(&mut self, x: i32, y: i32, key: u32) -> bool {
+        match self.get(x, y) {
+            Some(&tile) if tile.lock == Some(key) => self.set(x, y, Tile { lock: None, ..tile }),
+            _ => false,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use map::Map;
+    extern crate rand;
+
+    use map::{Map, Tile, TileKind};
+    use self::rand::{Rng, SeedableRng, StdRng};
 
     #[test]
     fn tiles_not_equal() {
         let mut map = Map::new(16, 16);
-        map.data[2][3].transparent = false;
-        map.data[3][2].transparent = true;
-        assert_ne!(map.data[2][3], map.data[3][2]);
+        map.get_mut(3, 2).unwrap().transparent = false;
+        map.get_mut(2, 3).unwrap().transparent = true;
+        assert_ne!(map.get(3, 2), map.get(2, 3));
+    }
+
+    #[test]
+    fn storage_is_row_major() {
+        let mut map = Map::new(3, 2);
+        assert!(map.set(2, 0, Tile::from_kind(TileKind::Floor)));
+        let floors: Vec<_> = map.iter()
+            .filter(|&(_, _, tile)| tile.walkable)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert_eq!(floors, vec![(2, 0)]);
+        assert_eq!((map.width(), map.height()), (3, 2));
+    }
+
+    #[test]
+    fn neighbours_stay_on_the_map() {
+        let map = Map::new(4, 4);
+        assert_eq!(map.neighbours(0, 0).collect::<Vec<_>>(), vec![(1, 0), (1, 1), (0, 1)]);
+        assert_eq!(map.neighbours(1, 1).count(), 8);
+        assert_eq!(map.cardinal_neighbours(3, 3).collect::<Vec<_>>(), vec![(3, 2), (2, 3)]);
+        assert_eq!(map.neighbours(i32::MAX, i32::MIN).count(), 0);
+    }
+
+    #[test]
+    fn doors_open_close_and_lock() {
+        let mut map = Map::new(3, 1);
+        map.set(0, 0, Tile::from_kind(TileKind::DoorClosed));
+        map.set(1, 0, Tile { lock: Some(7), ..Tile::from_kind(TileKind::DoorClosed) });
+
+        assert!(!map.get(0, 0).unwrap().transparent);
+        assert!(map.open_door(0, 0));
+        assert!(map.get(0, 0).unwrap().transparent);
+        assert!(map.is_walkable(0, 0));
+        assert!(!map.open_door(0, 0));
+        assert!(map.close_door(0, 0));
+        assert!(!map.is_walkable(0, 0));
+
+        // Walls aren't doors
+        assert!(!map.open_door(2, 0));
+
+        assert!(!map.open_door(1, 0));
+        assert!(!map.unlock(1, 0, 3));
+        assert!(map.unlock(1, 0, 7));
+        assert!(map.open_door(1, 0));
+    }
+
+    #[test]
+    fn revision_only_moves_when_a_tile_changes() {
+        let mut map = Map::new(3, 1);
+        let start = map.revision();
+        map.get(0, 0);
+        map.get_mut(0, 0);
+        map.set(0, 0, Tile::new());
+        assert!(!map.unlock(0, 0, 1));
+        assert_eq!(map.revision(), start);
+
+        map.set(0, 0, Tile::from_kind(TileKind::DoorClosed));
+        let closed = map.revision();
+        assert_ne!(closed, start);
+        assert!(map.lock(0, 0, 1));
+        let locked = map.revision();
+        assert_ne!(locked, closed);
+        assert!(!map.open_door(0, 0));
+        assert_eq!(map.revision(), locked);
+        assert!(map.unlock(0, 0, 1));
+        assert_ne!(map.revision(), locked);
+    }
+
+    /// Throw a mix of nearby and extreme coordinates at every accessor;
+    /// nothing may panic, and only in-bounds positions may return a tile.
+    #[test]
+    fn no_coordinate_panics() {
+        let mut rng = StdRng::from_seed(&[0x5eed][..]);
+        let extremes = [i32::MIN, i32::MIN + 1, -2, -1, 0, 1, 5, 6, 7, i32::MAX - 1, i32::MAX];
+
+        for &(width, height) in [(0, 0), (1, 1), (7, 5), (1, 9)].iter() {
+            let mut map = Map::new(width, height);
+
+            let mut coords: Vec<(i32, i32)> = Vec::new();
+            for &x in extremes.iter() {
+                for &y in extremes.iter() {
+                    coords.push((x, y));
+                }
+            }
+            for _ in 0..10000 {
+                coords.push((rng.gen(), rng.gen()));
+                coords.push((rng.gen_range(-20, 20), rng.gen_range(-20, 20)));
+            }
+
+            for &(x, y) in coords.iter() {
+                let inside = x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height;
+                assert_eq!(map.in_bounds(x, y), inside);
+                assert_eq!(map.get(x, y).is_some(), inside);
+                assert_eq!(map.get_mut(x, y).is_some(), inside);
+                assert_eq!(map.set(x, y, Tile::new()), inside);
+                assert!(map.is_wall(x, y));
+                assert!(!map.is_walkable(x, y));
+                assert!(map.neighbours(x, y).all(|(nx, ny)| map.get(nx, ny).is_some()));
+                assert!(map.cardinal_neighbours(x, y).all(|(nx, ny)| map.get(nx, ny).is_some()));
+            }
+        }
     }
 }
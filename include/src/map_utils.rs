@@ -3,16 +3,21 @@ extern crate std;
 extern crate specs;
 
 // use rand::Rng;
-use map_utils::rand::Rng;
+use map_utils::rand::{Rng, SeedableRng, StdRng};
 // I have absolutely no idea why it thinks rand is in map_utils...
 
 use std::cmp;
-use specs::World;
+use std::collections::{HashSet, VecDeque};
+use specs::{Join, World};
 
-use map::Map;
-use entity::{Color};
-use component::{BaseEntity, Position, MoveDelta, Blocking};
+use map::{Map, Tile, TileKind};
+use prefab::Prefab;
+use corridor::{carve_corridor, pick_loops, CorridorStyle};
+use entity::{Color, Attributes};
+use spawn::{spawn, SpawnKind, SpawnList, SpawnTable};
+use component::{BaseEntity, Position, Blocking, Item, Key};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Rect {
     x1: i32,
     y1: i32,
@@ -26,121 +31,774 @@ impl Rect {
     }
 
     /// Returns true if this rectangle intersects with another one
-    pub fn intersects(self: &Self, other: &Rect) -> bool {
+    pub fn intersects(&self, other: &Rect) -> bool {
         self.x1 <= other.x2 && self.x2 >= other.x1 &&
             self.y1 <= other.y2 && self.y2 >= other.y1
     }
 
-    pub fn center(self: &Self) -> (i32, i32) {
+    pub fn center(&self) -> (i32, i32) {
         ((self.x1 + self.x2) / 2,
          (self.y1 + self.y2) / 2)
     }
+
+    /// Top left (inclusive) and bottom right (exclusive) of the area `make_room` would carve
+    pub fn corners(&self) -> ((i32, i32), (i32, i32)) {
+        ((self.x1, self.y1), (self.x2, self.y2))
+    }
+
+    /// Returns true if the point is inside the area `make_room` would carve
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x1 && x < self.x2 && y >= self.y1 && y < self.y2
+    }
 }
 
-pub fn place_entities(room: &Rect, world: &mut World, max_monsters_per_room: usize) {
-    let mut rng = rand::thread_rng();
-    let num_monsters = rng.gen_range::<usize>(0, max_monsters_per_room);
+/// How many random spots in a room to try before giving up on spawning something there.
+const SPAWN_ATTEMPTS: usize = 20;
+
+/// Every tile with something blocking on it.
+pub fn blocked_tiles(world: &World) -> HashSet<(i32, i32)> {
+    let positions = world.read::<Position>();
+    let blocking = world.read::<Blocking>();
+    (&positions, &blocking).join().map(|(pos, _)| (pos.x, pos.y)).collect()
+}
 
-    // TODO I'm sure this is an anti-pattern. Use collect().uniq() or something.
-    let mut mobs = Vec::new();
-    for _ in 0..num_monsters {
-        // Choose a random location in this room
+/// Pick a random walkable tile in the room which isn't in `occupied`.
+/// Gives up after `SPAWN_ATTEMPTS` tries, so a full room returns `None`.
+pub fn find_spawn_point<R: Rng>(map: &Map, room: &Rect, occupied: &HashSet<(i32, i32)>, rng: &mut R)
+    -> Option<(i32, i32)> {
+    if room.x1 >= room.x2 || room.y1 >= room.y2 {
+        return None;
+    }
+    for _ in 0..SPAWN_ATTEMPTS {
         let x = rng.gen_range::<i32>(room.x1, room.x2);
         let y = rng.gen_range::<i32>(room.y1, room.y2);
-
-        if !mobs.contains(&(x, y)) {
-            mobs.push((x, y));
+        if map.is_walkable(x, y) && !occupied.contains(&(x, y)) {
+            return Some((x, y));
         }
     }
+    None
+}
 
-    for &(x, y) in mobs.iter() {
-        world.create_entity()
-            .with(Position { x: x, y: y })
-            .with(Blocking)
-            .with(MoveDelta { dx: 0, dy: 0 })
-            .with(BaseEntity {
-                    fg: Color::Green,
-                    bg: Color::Default,
-                    glyph: 'o',
-                    blocks: true,
-                    name: String::from("Orc"),
-                })
-            .build();
+/// Fill a room with monsters and items from the spawn table.
+/// Nothing is put on a tile in `occupied`, and everything placed is added to it.
+pub fn place_entities<R: Rng>(map: &Map, room: &Rect, world: &mut World, table: &SpawnTable, depth: u32,
+                              occupied: &mut HashSet<(i32, i32)>, rng: &mut R) {
+    for &kind in [SpawnKind::Monster, SpawnKind::Item].iter() {
+        for _ in 0..table.roll_count(kind, depth, rng) {
+            let (x, y) = match find_spawn_point(map, room, occupied, rng) {
+                Some(point) => point,
+                None => {
+                    debug!("No room left for a {:?} in {:?}", kind, room);
+                    break;
+                },
+            };
+            if let Some(name) = table.pick(kind, depth, rng) {
+                spawn(world, name, x, y);
+                occupied.insert((x, y));
+            }
+        }
     }
 }
 
 pub fn make_h_tunnel(map: &mut Map, x1: i32, x2: i32, y: i32) {
     for x in cmp::min(x1, x2)..cmp::max(x1, x2)+1 {
-        let tile = map.at_mut(x, y);
-        tile.walkable = true;
-        tile.transparent = true;
+        map.set(x, y, Tile::from_kind(TileKind::Floor));
     }
 }
 
 pub fn make_v_tunnel(map: &mut Map, y1: i32, y2: i32, x: i32) {
     for y in cmp::min(y1, y2)..cmp::max(y1, y2)+1 {
-        let tile = &mut map.at_mut(x, y);
-        tile.walkable = true;
-        tile.transparent = true;
+        map.set(x, y, Tile::from_kind(TileKind::Floor));
     }
 }
 
 pub fn make_room(map: &mut Map, room: &Rect) {
     for x in room.x1..room.x2 {
         for y in room.y1..room.y2 {
-            let tile = &mut map.at_mut(x, y);
-            tile.walkable = true;
-            tile.transparent = true;
+            map.set(x, y, Tile::from_kind(TileKind::Floor));
+        }
+    }
+}
+
+/// Carve the shortest path from `start` to the nearest walkable tile, without
+/// going through any of the `avoid` areas or the outer edge of the map.
+/// Returns false if there was nothing to connect to.
+pub fn connect_to_nearest_floor(map: &mut Map, start: (i32, i32), avoid: &[Rect]) -> bool {
+    let allowed = |map: &Map, (x, y): (i32, i32)| {
+        x > 0 && y > 0 && x < map.width() - 1 && y < map.height() - 1 &&
+            !avoid.iter().any(|r| r.contains(x, y))
+    };
+    if !allowed(map, start) {
+        return false;
+    }
+
+    // Breadth first search, remembering how we got to each tile.
+    let mut came_from = vec![None; (map.width() * map.height()) as usize];
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::new();
+    seen.insert(start);
+    queue.push_back(start);
+
+    while let Some((x, y)) = queue.pop_front() {
+        if (x, y) != start && map.is_walkable(x, y) {
+            // Found it, so walk back to the start carving as we go.
+            let mut at = (x, y);
+            while let Some(prev) = came_from[(at.1 * map.width() + at.0) as usize] {
+                at = prev;
+                if !map.is_walkable(at.0, at.1) {
+                    map.set(at.0, at.1, Tile::from_kind(TileKind::Floor));
+                }
+            }
+            return true;
+        }
+        let next: Vec<_> = map.cardinal_neighbours(x, y).collect();
+        for n in next {
+            if allowed(map, n) && seen.insert(n) {
+                came_from[(n.1 * map.width() + n.0) as usize] = Some((x, y));
+                queue.push_back(n);
+            }
         }
     }
+    false
 }
 
-/// Returns player starting position
-pub fn make_map(map: &mut Map, world: &mut World) -> (i32, i32) {
-    let room_max_size = 10;
-    let room_min_size = 6;
-    let max_rooms = 30;
-    let max_monsters_per_room = 4;
+/// Try to stamp one of the prefabs, randomly rotated and mirrored, somewhere it fits
+/// in solid rock without overlapping anything in `avoid`, then join each of its
+/// entrances up to the rest of the map. Anywhere that can't be done is passed over.
+/// Returns the area it covers and the names and positions of everything it wants
+/// spawned, if it was placed.
+pub fn place_vault<R: Rng>(map: &mut Map, prefabs: &[Prefab], avoid: &[Rect], rng: &mut R)
+    -> Option<(Rect, SpawnList)> {
+    let attempts = 20;
+    if prefabs.is_empty() {
+        return None;
+    }
+
+    for _ in 0..attempts {
+        let mut prefab = prefabs[rng.gen_range(0, prefabs.len())].clone();
+        for _ in 0..rng.gen_range(0, 4) {
+            prefab = prefab.rotated();
+        }
+        if rng.gen() {
+            prefab = prefab.mirrored();
+        }
+
+        // Leave a margin around the edge of the map for corridors to the entrances.
+        let (w, h) = (prefab.width(), prefab.height());
+        if w + 4 > map.width() || h + 4 > map.height() {
+            continue;
+        }
+        let x = rng.gen_range::<i32>(2, map.width() - w - 1);
+        let y = rng.gen_range::<i32>(2, map.height() - h - 1);
+        let rect = Rect::new(x, y, w as usize, h as usize);
+        if avoid.iter().any(|other| other.intersects(&rect)) {
+            continue;
+        }
+        // Anything other than rock here means we'd be cutting through a corridor.
+        let solid = (x - 1..x + w + 1).all(|tx| (y - 1..y + h + 1).all(|ty| map.is_wall(tx, ty)));
+        if !solid {
+            continue;
+        }
+
+        // Work on a copy, so a vault which can't be reached leaves nothing behind.
+        let mut stamped = map.clone();
+        let spawns = prefab.stamp(&mut stamped, x, y);
+        let mut keep_out = avoid.to_vec();
+        keep_out.push(rect);
+        let entrances = prefab.entrances();
+        let joined = !entrances.is_empty() && entrances.into_iter().all(|((ex, ey), (dx, dy))| {
+            connect_to_nearest_floor(&mut stamped, (x + ex + dx, y + ey + dy), &keep_out)
+        });
+        if !joined {
+            debug!("Vault {} at {:?} can't be joined up", prefab.name, (x, y));
+            continue;
+        }
+
+        info!("Placing vault {} at {:?}", prefab.name, (x, y));
+        *map = stamped;
+        return Some((rect, spawns));
+    }
+    None
+}
 
-    let map_height = map.data.len();
-    let map_width = map.data[0].len();
+/// Put closed doors wherever a corridor meets a room through a one tile gap in its wall.
+/// Returns the positions of the new doors.
+pub fn place_doors(map: &mut Map, rooms: &[Rect]) -> Vec<(i32, i32)> {
+    let mut doors = Vec::new();
+    for room in rooms.iter() {
+        // The ring of tiles just outside the carved area, without the corners.
+        // Along the top and bottom a doorway needs wall to its left and right,
+        // along the sides it needs wall above and below.
+        let mut candidates = Vec::new();
+        for x in room.x1..room.x2 {
+            candidates.push((x, room.y1 - 1, (1, 0)));
+            candidates.push((x, room.y2, (1, 0)));
+        }
+        for y in room.y1..room.y2 {
+            candidates.push((room.x1 - 1, y, (0, 1)));
+            candidates.push((room.x2, y, (0, 1)));
+        }
+
+        for (x, y, (dx, dy)) in candidates {
+            let is_floor = map.get(x, y).is_some_and(|tile| tile.kind == TileKind::Floor);
+            if is_floor && map.is_wall(x - dx, y - dy) && map.is_wall(x + dx, y + dy) {
+                map.set(x, y, Tile::from_kind(TileKind::DoorClosed));
+                doors.push((x, y));
+            }
+        }
+    }
+    doors
+}
+
+/// Lock one of the doors, and leave the key for it somewhere in `room`.
+/// The key goes on a free tile if there is one, otherwise in the middle of the room.
+pub fn lock_door<R: Rng>(map: &mut Map, world: &mut World, door: (i32, i32), room: &Rect, key_id: u32,
+                         occupied: &mut HashSet<(i32, i32)>, rng: &mut R) {
+    map.lock(door.0, door.1, key_id);
+
+    let (x, y) = find_spawn_point(map, room, occupied, rng).unwrap_or_else(|| room.center());
+    occupied.insert((x, y));
+    world.create_entity()
+        .with(Position { x, y })
+        .with(Item)
+        .with(Key { id: key_id })
+        .with(BaseEntity {
+                fg: Color::Yellow,
+                bg: Color::Default,
+                attrs: Attributes::BOLD,
+                glyph: '-',
+                blocks: false,
+                name: String::from("Brass Key"),
+            })
+        .build();
+}
+
+/// Knobs for `generate_map` and `make_map`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapConfig {
+    pub room_min_size: usize,
+    pub room_max_size: usize,
+    pub max_rooms: usize,
+    /// Maps with fewer rooms than this are thrown away and generated again.
+    pub min_rooms: usize,
+    /// How far down the dungeon this level is, starting at 1. Decides what gets spawned.
+    pub depth: u32,
+    pub max_vaults: usize,
+    /// How rooms are joined up.
+    pub corridor: CorridorStyle,
+    /// Each room is joined to the one generated before it, which makes a tree.
+    /// This many extra corridors are added between nearby rooms to make loops.
+    pub extra_connections: usize,
+    /// How far apart (in steps between centres) two rooms can be to get an extra corridor.
+    pub loop_distance: i32,
+    /// How many maps to try before giving up and carving a single big room.
+    pub max_attempts: usize,
+}
+
+impl Default for MapConfig {
+    fn default() -> Self {
+        MapConfig {
+            room_min_size: 6,
+            room_max_size: 10,
+            max_rooms: 30,
+            min_rooms: 2,
+            depth: 1,
+            max_vaults: 2,
+            corridor: CorridorStyle::LShaped,
+            extra_connections: 2,
+            loop_distance: 20,
+            max_attempts: 10,
+        }
+    }
+}
+
+/// Where everything ended up on a generated map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    pub rooms: Vec<Rect>,
+    pub vaults: Vec<Rect>,
+    pub doors: Vec<(i32, i32)>,
+    pub start: (i32, i32),
+    pub stairs: (i32, i32),
+    /// Things the vaults want spawned.
+    pub spawns: SpawnList,
+}
+
+/// Why a generated map isn't good enough to play on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapProblem {
+    /// Fewer rooms than the config asks for.
+    TooFewRooms(usize),
+    /// These rooms can't be reached from the start.
+    UnreachableRooms(Vec<Rect>),
+    /// Some of the floor inside these vaults can't be reached from the start.
+    UnreachableVaults(Vec<Rect>),
+    /// The stairs down aren't on the map, or can't be reached from the start.
+    UnreachableStairs,
+}
+
+/// A random number generator which always produces the same map for the same seed.
+pub fn seeded_rng(seed: usize) -> StdRng {
+    StdRng::from_seed(&[seed][..])
+}
+
+/// How many steps it takes to walk from `start` to every tile, indexed row-major.
+/// `None` means the tile can't be reached at all.
+/// Doors count as passable, even locked ones, since the key is always left
+/// in the starting room.
+pub fn distances_from(map: &Map, start: (i32, i32)) -> Vec<Option<u32>> {
+    let width = map.width();
+    let mut distances = vec![None; (width * map.height()) as usize];
+    let passable = |x: i32, y: i32| {
+        map.get(x, y).is_some_and(|tile| tile.walkable || tile.is_door())
+    };
+    if !passable(start.0, start.1) {
+        return distances;
+    }
+
+    let mut queue = VecDeque::new();
+    distances[(start.1 * width + start.0) as usize] = Some(0);
+    queue.push_back((start, 0));
+    while let Some(((x, y), distance)) = queue.pop_front() {
+        for (nx, ny) in map.cardinal_neighbours(x, y) {
+            let i = (ny * width + nx) as usize;
+            if distances[i].is_none() && passable(nx, ny) {
+                distances[i] = Some(distance + 1);
+                queue.push_back(((nx, ny), distance + 1));
+            }
+        }
+    }
+    distances
+}
+
+/// Every tile which can be reached on foot from `start`, indexed row-major.
+/// See `distances_from`.
+pub fn reachable_from(map: &Map, start: (i32, i32)) -> Vec<bool> {
+    distances_from(map, start).iter().map(Option::is_some).collect()
+}
+
+/// Numbers for judging how a generated map turned out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapStats {
+    pub rooms: usize,
+    /// Fraction of the map which can be walked on (counting doors).
+    pub walkable_ratio: f64,
+    /// Walkable tiles with only one walkable neighbour.
+    pub dead_ends: usize,
+    /// Steps from the start to the stairs, or `None` if they can't be reached.
+    pub stairs_distance: Option<u32>,
+}
+
+impl MapStats {
+    pub fn measure(map: &Map, layout: &Layout) -> Self {
+        let passable = |x: i32, y: i32| {
+            map.get(x, y).is_some_and(|tile| tile.walkable || tile.is_door())
+        };
+        let walkable: Vec<(i32, i32)> = map.iter()
+            .map(|(x, y, _)| (x, y))
+            .filter(|&(x, y)| passable(x, y))
+            .collect();
+        let dead_ends = walkable.iter()
+            .filter(|&&(x, y)| map.cardinal_neighbours(x, y).filter(|&(nx, ny)| passable(nx, ny)).count() == 1)
+            .count();
+        let area = (map.width() * map.height()).max(1);
+        let (sx, sy) = layout.stairs;
+        let stairs_distance = if map.in_bounds(sx, sy) {
+            distances_from(map, layout.start)[(sy * map.width() + sx) as usize]
+        } else {
+            None
+        };
+
+        MapStats {
+            rooms: layout.rooms.len(),
+            walkable_ratio: walkable.len() as f64 / f64::from(area),
+            dead_ends,
+            stairs_distance,
+        }
+    }
+}
+
+/// Check every room, everything inside the vaults, and the stairs can be reached from the start.
+pub fn validate(map: &Map, layout: &Layout, config: &MapConfig) -> Result<(), MapProblem> {
+    if layout.rooms.len() < config.min_rooms {
+        return Err(MapProblem::TooFewRooms(layout.rooms.len()));
+    }
+
+    let reached = reachable_from(map, layout.start);
+    let is_reached = |(x, y): (i32, i32)| {
+        map.in_bounds(x, y) && reached[(y * map.width() + x) as usize]
+    };
+    let unreachable: Vec<Rect> = layout.rooms.iter()
+        .filter(|room| !is_reached(room.center()))
+        .cloned()
+        .collect();
+    if !unreachable.is_empty() {
+        return Err(MapProblem::UnreachableRooms(unreachable));
+    }
+    let passable = |x: i32, y: i32| map.get(x, y).is_some_and(|tile| tile.walkable || tile.is_door());
+    let cut_off: Vec<Rect> = layout.vaults.iter()
+        .filter(|vault| {
+            (vault.x1..vault.x2).any(|x| (vault.y1..vault.y2).any(|y| passable(x, y) && !is_reached((x, y))))
+        })
+        .cloned()
+        .collect();
+    if !cut_off.is_empty() {
+        return Err(MapProblem::UnreachableVaults(cut_off));
+    }
+    if !is_reached(layout.stairs) {
+        return Err(MapProblem::UnreachableStairs);
+    }
+    Ok(())
+}
+
+/// Tunnel from each room which can't be reached to the closest one which can.
+pub fn repair(map: &mut Map, layout: &Layout) {
+    let reached = reachable_from(map, layout.start);
+    let is_reached = |(x, y): (i32, i32)| {
+        map.in_bounds(x, y) && reached[(y * map.width() + x) as usize]
+    };
+    let (connected, cut_off): (Vec<Rect>, Vec<Rect>) = layout.rooms.iter()
+        .partition(|room| is_reached(room.center()));
+
+    let distance = |a: (i32, i32), b: (i32, i32)| (a.0 - b.0).abs() + (a.1 - b.1).abs();
+    for room in cut_off {
+        let (x, y) = room.center();
+        if let Some(target) = connected.iter().min_by_key(|other| distance((x, y), other.center())) {
+            let (tx, ty) = target.center();
+            info!("Repairing map: joining room at {:?} to {:?}", (x, y), (tx, ty));
+            make_h_tunnel(map, x, tx, y);
+            make_v_tunnel(map, y, ty, tx);
+        }
+    }
+}
+
+/// Scatter rooms over the map, each joined to the one before it,
+/// plus a few extra corridors to make loops.
+fn carve_rooms<R: Rng>(map: &mut Map, config: &MapConfig, rng: &mut R) -> Vec<Rect> {
+    let map_width = map.width().max(0) as usize;
+    let map_height = map.height().max(0) as usize;
+    let room_max_size = config.room_max_size.max(config.room_min_size + 1);
 
     let mut rooms: Vec<Rect> = Vec::new();
-    for _ in 0..max_rooms {
-        let mut rng = rand::thread_rng();
-        let w = rng.gen_range::<usize>(room_min_size, room_max_size);
-        let h = rng.gen_range::<usize>(room_min_size, room_max_size);
+    for _ in 0..config.max_rooms {
+        let w = rng.gen_range::<usize>(config.room_min_size, room_max_size);
+        let h = rng.gen_range::<usize>(config.room_min_size, room_max_size);
+        if w + 1 >= map_width || h + 1 >= map_height {
+            // Doesn't fit on the map at all.
+            continue;
+        }
         let x = rng.gen_range::<i32>(0, (map_width - w) as i32 - 1);
         let y = rng.gen_range::<i32>(0, (map_height - h) as i32 - 1);
 
         let room = Rect::new(x, y, w, h);
         if rooms.iter().all(|it| !it.intersects(&room) ) {
             make_room(map, &room);
+            rooms.push(room);
+        }
+    }
 
-            // Join this room with the previous one
-            if let Some(old_room) = rooms.last() {
-                let (new_x, new_y) = room.center();
-                let (old_x, old_y) = old_room.center();
-                if rng.gen() {
-                    // Horizontal first
-                    info!("Horizontal first");
-                    make_h_tunnel(map, old_x, new_x, old_y);
-                    make_v_tunnel(map, old_y, new_y, new_x);
-                }
-                else {
-                    // Vertical first
-                    info!("Vertical first");
-                    make_v_tunnel(map, old_y, new_y, old_x);
-                    make_h_tunnel(map, old_x, new_x, new_y);
-                }
+    // Corridors are dug once all the rooms are in, so they can steer around them.
+    for pair in rooms.windows(2) {
+        carve_corridor(map, pair[0].center(), pair[1].center(), config.corridor, &rooms, rng);
+    }
+    for (i, j) in pick_loops(&rooms, config.extra_connections, config.loop_distance, rng) {
+        debug!("Adding a loop between rooms {} and {}", i, j);
+        carve_corridor(map, rooms[i].center(), rooms[j].center(), config.corridor, &rooms, rng);
+    }
+    rooms
+}
+
+/// One room filling the whole map, for when nothing better could be generated.
+fn fallback_layout(map: &mut Map) -> Layout {
+    let (width, height) = (map.width(), map.height());
+    let room = if width >= 3 && height >= 3 {
+        Rect::new(1, 1, (width - 2) as usize, (height - 2) as usize)
+    } else {
+        Rect::new(0, 0, width.max(0) as usize, height.max(0) as usize)
+    };
+    make_room(map, &room);
+    let start = (room.x1, room.y1);
+    let stairs = (cmp::max(room.x1, room.x2 - 1), cmp::max(room.y1, room.y2 - 1));
+    map.set(stairs.0, stairs.1, Tile::from_kind(TileKind::StairsDown));
+    Layout { rooms: vec![room], vaults: Vec::new(), doors: Vec::new(), start, stairs, spawns: Vec::new() }
+}
+
+/// Carve out a map, without putting anything on it.
+/// Maps are checked with `validate` (and `repair`ed if that helps), and thrown away
+/// if they still aren't any good. If no attempt works out, the whole map becomes
+/// one big room, so this always returns something playable.
+pub fn generate_map<R: Rng>(map: &mut Map, config: &MapConfig, prefabs: &[Prefab], rng: &mut R) -> Layout {
+    let (width, height) = (map.width().max(0) as usize, map.height().max(0) as usize);
+
+    for attempt in 0..config.max_attempts {
+        *map = Map::new(width, height);
+        let rooms = carve_rooms(map, config, rng);
+        // Even with `min_rooms` at 0, a map needs somewhere to start.
+        if rooms.is_empty() || rooms.len() < config.min_rooms {
+            debug!("Attempt {}: only {} rooms", attempt, rooms.len());
+            continue;
+        }
+
+        let mut vaults: Vec<Rect> = Vec::new();
+        let mut spawns = Vec::new();
+        for _ in 0..config.max_vaults {
+            let avoid: Vec<Rect> = rooms.iter().chain(vaults.iter()).cloned().collect();
+            if let Some((vault, mut vault_spawns)) = place_vault(map, prefabs, &avoid, rng) {
+                vaults.push(vault);
+                spawns.append(&mut vault_spawns);
             }
+        }
 
-            place_entities(&room, world, max_monsters_per_room);
+        // Stairs go as far along the chain of rooms from the start as possible.
+        let start = rooms[0].center();
+        let stairs = rooms[rooms.len() - 1].center();
+        map.set(stairs.0, stairs.1, Tile::from_kind(TileKind::StairsDown));
 
-            rooms.push(room);
+        let mut layout = Layout { rooms, vaults, doors: Vec::new(), start, stairs, spawns };
+        let mut result = validate(map, &layout, config);
+        if let Err(MapProblem::UnreachableRooms(_)) = result {
+            repair(map, &layout);
+            result = validate(map, &layout, config);
+        }
+        match result {
+            Ok(()) => {
+                layout.doors = place_doors(map, &layout.rooms);
+                return layout;
+            },
+            Err(problem) => debug!("Attempt {}: {:?}", attempt, problem),
+        }
+    }
+
+    warn!("Couldn't generate a good {}x{} map, falling back to a single room", width, height);
+    *map = Map::new(width, height);
+    fallback_layout(map)
+}
+
+/// Generate a map and fill it with monsters, vault contents and a locked door.
+pub fn make_map<R: Rng>(map: &mut Map, world: &mut World, config: &MapConfig, table: &SpawnTable,
+                        rng: &mut R) -> Layout {
+    let layout = generate_map(map, config, &Prefab::builtin(), rng);
+
+    for &(ref name, x, y) in layout.spawns.iter() {
+        spawn(world, name, x, y);
+    }
+
+    // Keep the start clear for the player.
+    let mut occupied = blocked_tiles(world);
+    occupied.insert(layout.start);
+    for room in layout.rooms.iter() {
+        place_entities(map, room, world, table, config.depth, &mut occupied, rng);
+    }
+
+    // The key always starts in the player's room, so it can always be reached.
+    if !layout.doors.is_empty() {
+        let door = layout.doors[rng.gen_range(0, layout.doors.len())];
+        lock_door(map, world, door, &layout.rooms[0], 1, &mut occupied, rng);
+    }
+
+    layout
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use specs::{Join, World};
+    use component::{register_all, Position};
+    use map::{Map, Tile, TileKind};
+    use map_utils::{connect_to_nearest_floor, find_spawn_point, generate_map, make_h_tunnel, make_map,
+                    make_room, place_doors, place_vault, reachable_from, repair, seeded_rng, validate, Layout,
+                    MapConfig, MapProblem, MapStats, Rect};
+    use prefab::Prefab;
+    use corridor::CorridorStyle;
+    use spawn::SpawnTable;
+
+    #[test]
+    fn doors_go_in_single_width_gaps() {
+        let mut map = Map::new(12, 7);
+        let room = Rect::new(1, 1, 4, 4);
+        make_room(&mut map, &room);
+        // One corridor leaving through the east wall...
+        make_h_tunnel(&mut map, 4, 10, 2);
+        // ...and one running along the outside of the south wall, which isn't a doorway.
+        make_h_tunnel(&mut map, 0, 6, 5);
+
+        assert_eq!(place_doors(&mut map, &[room]), vec![(5, 2)]);
+        assert_eq!(map.get(5, 2).unwrap().kind, TileKind::DoorClosed);
+    }
+
+    #[test]
+    fn connects_around_avoided_areas() {
+        // A room on the left, a vault in the middle, and a doorway on the right
+        // of the vault which has to go the long way round to reach the room.
+        let mut map = Map::new(12, 9);
+        make_room(&mut map, &Rect::new(1, 1, 2, 7));
+        let vault = Rect::new(4, 2, 5, 5);
+
+        assert!(connect_to_nearest_floor(&mut map, (9, 4), &[vault]));
+        assert!(map.is_walkable(9, 4));
+        for y in 2..7 {
+            for x in 4..9 {
+                assert!(!map.is_walkable(x, y), "carved through the vault at {:?}", (x, y));
+            }
+        }
+
+        // Nothing to reach at all
+        let mut empty = Map::new(5, 5);
+        assert!(!connect_to_nearest_floor(&mut empty, (2, 2), &[]));
+    }
+
+    #[test]
+    fn validation_finds_and_repairs_cut_off_rooms() {
+        let mut map = Map::new(20, 8);
+        let rooms = vec![Rect::new(1, 1, 4, 4), Rect::new(12, 2, 5, 4)];
+        for room in rooms.iter() {
+            make_room(&mut map, room);
         }
+        let layout = Layout {
+            start: rooms[0].center(),
+            stairs: rooms[1].center(),
+            rooms: rooms.clone(),
+            vaults: Vec::new(),
+            doors: Vec::new(),
+            spawns: Vec::new(),
+        };
+        let config = MapConfig::default();
+
+        assert_eq!(validate(&map, &layout, &config), Err(MapProblem::UnreachableRooms(vec![rooms[1]])));
+        repair(&mut map, &layout);
+        assert_eq!(validate(&map, &layout, &config), Ok(()));
+
+        // The repaired corridor runs straight along the stairs' row.
+        let stats = MapStats::measure(&map, &layout);
+        assert_eq!(stats.rooms, 2);
+        assert_eq!(stats.dead_ends, 0);
+        assert_eq!(stats.stairs_distance, Some(12));
+
+        // A sealed vault is no good either.
+        let vault = Rect::new(8, 1, 3, 2);
+        make_room(&mut map, &vault);
+        let sealed = Layout { vaults: vec![vault], ..layout.clone() };
+        assert_eq!(validate(&map, &sealed, &config), Err(MapProblem::UnreachableVaults(vec![vault])));
+
+        let one_room = Layout { rooms: vec![rooms[0]], ..layout };
+        assert_eq!(validate(&map, &one_room, &config), Err(MapProblem::TooFewRooms(1)));
     }
 
-    rooms[0].center()
+    #[test]
+    fn vaults_are_only_placed_where_they_can_be_reached() {
+        // Solid rock, so there is nothing to join a vault up to.
+        let mut map = Map::new(30, 30);
+        let prefabs = Prefab::builtin();
+        assert_eq!(place_vault(&mut map, &prefabs, &[], &mut seeded_rng(0)), None);
+        assert!(map.iter().all(|(_, _, tile)| tile.kind == TileKind::Wall));
+
+        let room = Rect::new(1, 1, 3, 28);
+        make_room(&mut map, &room);
+        let (vault, _) = place_vault(&mut map, &prefabs, &[], &mut seeded_rng(0)).unwrap();
+        let layout = Layout {
+            rooms: vec![room],
+            vaults: vec![vault],
+            doors: Vec::new(),
+            start: room.center(),
+            stairs: room.center(),
+            spawns: Vec::new(),
+        };
+        assert_eq!(validate(&map, &layout, &MapConfig { min_rooms: 1, ..MapConfig::default() }), Ok(()));
+    }
+
+    #[test]
+    fn generated_maps_are_always_connected() {
+        let config = MapConfig::default();
+        let prefabs = Prefab::builtin();
+        for seed in 0..2000 {
+            let mut map = Map::new(32, 32);
+            let layout = generate_map(&mut map, &config, &prefabs, &mut seeded_rng(seed));
+            assert_eq!(validate(&map, &layout, &config), Ok(()), "seed {}", seed);
+            assert_eq!(map.get(layout.stairs.0, layout.stairs.1).unwrap().kind, TileKind::StairsDown);
+        }
+    }
 
+    #[test]
+    fn every_corridor_style_makes_connected_maps() {
+        let prefabs = Prefab::builtin();
+        for &corridor in [CorridorStyle::LShaped, CorridorStyle::Straight,
+                          CorridorStyle::Drunken, CorridorStyle::AStar].iter() {
+            let config = MapConfig { corridor, extra_connections: 4, ..MapConfig::default() };
+            for seed in 0..200 {
+                let mut map = Map::new(48, 32);
+                let layout = generate_map(&mut map, &config, &prefabs, &mut seeded_rng(seed));
+                assert_eq!(validate(&map, &layout, &config), Ok(()), "{:?} with seed {}", corridor, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_map() {
+        let config = MapConfig::default();
+        let prefabs = Prefab::builtin();
+        let mut a = Map::new(40, 30);
+        let mut b = Map::new(40, 30);
+        let layout_a = generate_map(&mut a, &config, &prefabs, &mut seeded_rng(7));
+        let layout_b = generate_map(&mut b, &config, &prefabs, &mut seeded_rng(7));
+        assert_eq!(layout_a, layout_b);
+        assert!(a.iter().zip(b.iter()).all(|((_, _, x), (_, _, y))| x == y));
+    }
+
+    #[test]
+    fn tiny_maps_fall_back_to_one_room() {
+        let config = MapConfig::default();
+        for &(width, height) in [(0, 0), (1, 1), (3, 3), (8, 12), (12, 12)].iter() {
+            let mut map = Map::new(width, height);
+            let layout = generate_map(&mut map, &config, &[], &mut seeded_rng(1));
+            assert_eq!(layout.rooms.len(), 1, "{}x{}", width, height);
+            if width >= 3 && height >= 3 {
+                assert!(reachable_from(&map, layout.start).iter().any(|&r| r));
+            }
+        }
+
+        // No rooms at all isn't good enough, even if it is allowed.
+        let config = MapConfig { min_rooms: 0, ..MapConfig::default() };
+        let mut map = Map::new(4, 4);
+        assert_eq!(generate_map(&mut map, &config, &[], &mut seeded_rng(1)).rooms.len(), 1);
+    }
+
+    #[test]
+    fn spawn_points_avoid_walls_and_occupied_tiles() {
+        // A 2x2 room with a pillar in it and one tile already taken.
+        let mut map = Map::new(4, 4);
+        let room = Rect::new(1, 1, 2, 2);
+        make_room(&mut map, &room);
+        map.set(1, 1, Tile::from_kind(TileKind::Wall));
+        let mut occupied = HashSet::new();
+        occupied.insert((2, 1));
+
+        let mut rng = seeded_rng(0);
+        for _ in 0..50 {
+            let point = find_spawn_point(&map, &room, &occupied, &mut rng);
+            assert!(point == Some((1, 2)) || point == Some((2, 2)) || point.is_none());
+        }
+        occupied.insert((1, 2));
+        occupied.insert((2, 2));
+        assert_eq!(find_spawn_point(&map, &room, &occupied, &mut rng), None);
+    }
+
+    #[test]
+    fn spawned_entities_never_share_tiles() {
+        let config = MapConfig { depth: 6, ..MapConfig::default() };
+        let table = SpawnTable::default();
+        for seed in 0..50 {
+            let mut world = World::new();
+            register_all(&mut world);
+            let mut map = Map::new(48, 32);
+            let layout = make_map(&mut map, &mut world, &config, &table, &mut seeded_rng(seed));
+
+            let positions = world.read::<Position>();
+            let mut seen = HashSet::new();
+            for pos in positions.join() {
+                assert!(map.is_walkable(pos.x, pos.y), "seed {}: spawned in a wall at {:?}", seed, pos);
+                assert!(seen.insert((pos.x, pos.y)), "seed {}: two things at {:?}", seed, pos);
+            }
+            assert!(!seen.contains(&layout.start), "seed {}: something is on the player", seed);
+        }
+    }
 }
@@ -0,0 +1,322 @@
+use entity::{Color, Rgb};
+use std::collections::HashMap;
+
+/// How many colours the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Only the eight basic curses colours.
+    Basic,
+    /// The xterm 256 colour palette.
+    Indexed256,
+    /// At least 256 colours which we are allowed to redefine,
+    /// so any RGB value can be shown exactly (until we run out of slots).
+    TrueColor,
+}
+
+impl ColorMode {
+    /// Pick a mode from what curses tells us about the terminal.
+    pub fn from_capabilities(colors: i32, can_change_color: bool) -> Self {
+        if colors >= 256 && can_change_color {
+            ColorMode::TrueColor
+        } else if colors >= 256 {
+            ColorMode::Indexed256
+        } else {
+            ColorMode::Basic
+        }
+    }
+}
+
+/// RGB values of the eight basic colours, in curses order.
+const BASIC_RGB: [(u8, u8, u8); 8] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+];
+
+/// Channel levels of the 6x6x6 colour cube in the xterm 256 colour palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The first colour number we are willing to redefine in `TrueColor` mode.
+/// The low 16 are left alone so the basic colours still look right.
+const FIRST_CUSTOM_COLOR: i16 = 16;
+
+fn distance(a: Rgb, b: (u8, u8, u8)) -> i32 {
+    let dr = i32::from(a.r) - i32::from(b.0);
+    let dg = i32::from(a.g) - i32::from(b.1);
+    let db = i32::from(a.b) - i32::from(b.2);
+    dr * dr + dg * dg + db * db
+}
+
+/// The closest of the eight basic colours.
+pub fn nearest_basic(rgb: Rgb) -> i16 {
+    (0..BASIC_RGB.len())
+        .min_by_key(|&i| distance(rgb, BASIC_RGB[i]))
+        .unwrap() as i16
+}
+
+/// The closest entry of the xterm 256 colour palette, ignoring the first 16
+/// since terminals disagree on what they look like.
+pub fn nearest_256(rgb: Rgb) -> i16 {
+    let level = |c: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| (i32::from(CUBE_LEVELS[i]) - i32::from(c)).abs())
+            .unwrap()
+    };
+    let (r, g, b) = (level(rgb.r), level(rgb.g), level(rgb.b));
+    let cube = (CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]);
+    let cube_index = 16 + 36 * r + 6 * g + b;
+
+    // The greyscale ramp runs from 8 to 238 in steps of 10.
+    let average = (u32::from(rgb.r) + u32::from(rgb.g) + u32::from(rgb.b)) / 3;
+    let grey_step = (average.saturating_sub(3) / 10).min(23) as u8;
+    let grey_level = 8 + 10 * grey_step;
+    let grey = (grey_level, grey_level, grey_level);
+    let grey_index = 232 + grey_step as usize;
+
+    if distance(rgb, grey) < distance(rgb, cube) {
+        grey_index as i16
+    } else {
+        cube_index as i16
+    }
+}
+
+/// Roughly what a colour looks like, for drawing outside the terminal.
+/// `Default` is up to the terminal, so has no answer.
+pub fn to_rgb(color: &Color) -> Option<Rgb> {
+    let index = match *color {
+        Color::Default => return None,
+        Color::Rgb(rgb) => return Some(rgb),
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::White => 7,
+    };
+    let (r, g, b) = BASIC_RGB[index];
+    Some(Rgb { r, g, b })
+}
+
+/// Curses takes colour components in the range 0-1000.
+fn to_curses_component(c: u8) -> i16 {
+    (i32::from(c) * 1000 / 255) as i16
+}
+
+/// The result of looking a colour up in a `Palette`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResolvedColor {
+    /// Curses colour number to use.
+    pub index: i16,
+    /// If set, the colour number has to be defined with `init_color` using
+    /// these components before it is used.
+    pub define: Option<(i16, i16, i16)>,
+}
+
+/// Maps our colours onto curses colour numbers for a given `ColorMode`.
+pub struct Palette {
+    mode: ColorMode,
+    max_colors: i16,
+    custom: HashMap<Rgb, i16>,
+}
+
+impl Palette {
+    /// `max_colors` is the number of colours the terminal reports (`COLORS`).
+    pub fn new(mode: ColorMode, max_colors: i32) -> Self {
+        Palette {
+            mode,
+            max_colors: max_colors.min(i32::from(i16::MAX)) as i16,
+            custom: HashMap::new(),
+        }
+    }
+
+    pub fn mode(&self) -> ColorMode {
+        self.mode
+    }
+
+    pub fn resolve(&mut self, color: &Color) -> ResolvedColor {
+        let index = match *color {
+            Color::Default => -1,
+            Color::Black => 0,
+            Color::Red => 1,
+            Color::Green => 2,
+            Color::Yellow => 3,
+            Color::Blue => 4,
+            Color::Magenta => 5,
+            Color::Cyan => 6,
+            Color::White => 7,
+            Color::Rgb(rgb) => return self.resolve_rgb(rgb),
+        };
+        ResolvedColor { index, define: None }
+    }
+
+    /// The closest of the basic colours and the ones we have defined so far.
+    fn nearest_defined(&self, rgb: Rgb) -> i16 {
+        let basic = nearest_basic(rgb);
+        self.custom.iter()
+            .map(|(&custom, &index)| (distance(rgb, (custom.r, custom.g, custom.b)), index))
+            .chain(Some((distance(rgb, BASIC_RGB[basic as usize]), basic)))
+            .min()
+            .unwrap()
+            .1
+    }
+
+    fn resolve_rgb(&mut self, rgb: Rgb) -> ResolvedColor {
+        match self.mode {
+            ColorMode::Basic => ResolvedColor { index: nearest_basic(rgb), define: None },
+            ColorMode::Indexed256 => ResolvedColor { index: nearest_256(rgb), define: None },
+            ColorMode::TrueColor => {
+                if let Some(&index) = self.custom.get(&rgb) {
+                    return ResolvedColor { index, define: None };
+                }
+                let next = FIRST_CUSTOM_COLOR + self.custom.len() as i16;
+                if next >= self.max_colors {
+                    // Out of slots to redefine. The standard 256 colour numbers may have been
+                    // redefined already, so only the basic colours and our own can be trusted.
+                    return ResolvedColor { index: self.nearest_defined(rgb), define: None };
+                }
+                self.custom.insert(rgb, next);
+                let define = (to_curses_component(rgb.r),
+                              to_curses_component(rgb.g),
+                              to_curses_component(rgb.b));
+                ResolvedColor { index: next, define: Some(define) }
+            },
+        }
+    }
+}
+
+/// The result of looking a pair up in a `PairCache`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PairLookup {
+    pub id: i16,
+    /// The pair has to be (re)defined with `init_pair` before it is used.
+    pub needs_init: bool,
+    /// The pair used to mean other colours, so anything already on screen
+    /// drawn with it is about to change colour.
+    pub recycled: bool,
+}
+
+/// Hands out curses colour pair numbers for (fg, bg) colour number combinations.
+/// Once every pair is in use, the least recently used one is recycled,
+/// so we never run out. Anything already on screen using a recycled pair will
+/// change colour, which `PairLookup::recycled` warns about so it can be redrawn.
+/// With 32k pairs available on most terminals that is rare.
+pub struct PairCache {
+    capacity: usize,
+    pairs: HashMap<(i16, i16), (i16, u64)>,
+    clock: u64,
+}
+
+impl PairCache {
+    /// `max_pairs` is the number of pairs the terminal reports (`COLOR_PAIRS`).
+    /// Pair 0 is reserved by curses for the default colours.
+    pub fn new(max_pairs: i32) -> Self {
+        let capacity = (max_pairs.min(i32::from(i16::MAX)) - 1).max(1) as usize;
+        PairCache { capacity, pairs: HashMap::new(), clock: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    pub fn get(&mut self, fg: i16, bg: i16) -> PairLookup {
+        self.clock += 1;
+        let clock = self.clock;
+
+        if let Some(entry) = self.pairs.get_mut(&(fg, bg)) {
+            entry.1 = clock;
+            return PairLookup { id: entry.0, needs_init: false, recycled: false };
+        }
+
+        let recycled = self.pairs.len() >= self.capacity;
+        let id = if !recycled {
+            (self.pairs.len() + 1) as i16
+        } else {
+            let oldest = *self.pairs.iter()
+                .min_by_key(|&(_, &(_, used))| used)
+                .map(|(key, _)| key)
+                .unwrap();
+            let (id, _) = self.pairs.remove(&oldest).unwrap();
+            debug!("Recycling color pair {} ({:?})", id, oldest);
+            id
+        };
+        self.pairs.insert((fg, bg), (id, clock));
+        PairLookup { id, needs_init: true, recycled }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use entity::{Color, Rgb};
+    use palette::{nearest_256, nearest_basic, ColorMode, PairCache, Palette};
+
+    #[test]
+    fn detects_mode() {
+        assert_eq!(ColorMode::from_capabilities(8, true), ColorMode::Basic);
+        assert_eq!(ColorMode::from_capabilities(256, false), ColorMode::Indexed256);
+        assert_eq!(ColorMode::from_capabilities(256, true), ColorMode::TrueColor);
+    }
+
+    #[test]
+    fn nearest_colors() {
+        assert_eq!(nearest_basic(Rgb { r: 250, g: 10, b: 10 }), 1);
+        assert_eq!(nearest_basic(Rgb { r: 10, g: 10, b: 10 }), 0);
+        assert_eq!(nearest_256(Rgb { r: 255, g: 0, b: 0 }), 196);
+        assert_eq!(nearest_256(Rgb { r: 128, g: 128, b: 128 }), 244);
+    }
+
+    #[test]
+    fn truecolor_defines_slots_until_full() {
+        let mut palette = Palette::new(ColorMode::TrueColor, 18);
+        let orange = Color::rgb(255, 128, 0);
+
+        let first = palette.resolve(&orange);
+        assert_eq!(first.index, 16);
+        assert_eq!(first.define, Some((1000, 501, 0)));
+        assert_eq!(palette.resolve(&orange).define, None);
+
+        assert_eq!(palette.resolve(&Color::rgb(1, 2, 3)).index, 17);
+        // Out of slots, so fall back to whichever is nearest of the colours
+        // we defined and the basic ones, which nobody has redefined.
+        let fallback = palette.resolve(&Color::rgb(255, 0, 0));
+        assert_eq!((fallback.index, fallback.define), (1, None));
+        assert_eq!(palette.resolve(&Color::rgb(250, 120, 10)).index, 16);
+
+        assert_eq!(palette.resolve(&Color::Default).index, -1);
+        assert_eq!(palette.resolve(&Color::White).index, 7);
+    }
+
+    #[test]
+    fn basic_mode_degrades_rgb() {
+        let mut palette = Palette::new(ColorMode::Basic, 8);
+        assert_eq!(palette.resolve(&Color::rgb(0, 200, 200)).index, 6);
+    }
+
+    #[test]
+    fn pair_cache_recycles_least_recently_used() {
+        let mut cache = PairCache::new(3);
+        assert_eq!(cache.get(1, -1).id, 1);
+        let lookup = cache.get(2, -1);
+        assert_eq!(lookup.id, 2);
+        assert!(!lookup.recycled);
+        assert!(!cache.get(1, -1).needs_init);
+
+        // Full, and (2, -1) was used least recently.
+        let lookup = cache.get(3, -1);
+        assert_eq!(lookup.id, 2);
+        assert!(lookup.needs_init);
+        assert!(lookup.recycled);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(2, -1).needs_init);
+    }
+}
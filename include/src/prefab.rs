@@ -0,0 +1,302 @@
+use map::{Map, Tile, TileKind};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+/// What a single character of a prefab turns into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LegendEntry {
+    pub tile: TileKind,
+    /// Name of something to spawn on the tile, eg. "Orc".
+    pub spawn: Option<String>,
+    /// Lock the tile so it only opens for the `Key` with this id.
+    pub lock: Option<u32>,
+}
+
+/// A hand designed chunk of map, loaded from a text file like this:
+///
+/// ```text
+/// name: shrine
+/// # Wall
+/// . Floor
+/// + DoorClosed
+/// o Floor Orc
+/// ---
+/// ##+##
+/// #.o.#
+/// #####
+/// ```
+///
+/// Everything before the `---` is the legend, one character per line followed by
+/// a `TileKind` and optionally the name of something to spawn there.
+/// Everything after it is the layout. Spaces in the layout leave the map alone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prefab {
+    pub name: String,
+    legend: HashMap<char, LegendEntry>,
+    rows: Vec<Vec<char>>,
+}
+
+#[derive(Debug)]
+pub enum PrefabError {
+    Io(io::Error),
+    /// A problem on a particular (1-based) line of the file.
+    Syntax { line: usize, message: String },
+}
+
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PrefabError::Io(ref e) => write!(f, "could not read prefab: {}", e),
+            PrefabError::Syntax { line, ref message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl From<io::Error> for PrefabError {
+    fn from(e: io::Error) -> Self {
+        PrefabError::Io(e)
+    }
+}
+
+fn syntax_error<T>(line: usize, message: String) -> Result<T, PrefabError> {
+    Err(PrefabError::Syntax { line, message })
+}
+
+impl Prefab {
+    pub fn parse(text: &str) -> Result<Prefab, PrefabError> {
+        let mut name = String::from("unnamed");
+        let mut legend = HashMap::new();
+        let mut rows = Vec::new();
+        let mut in_layout = false;
+
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            if in_layout {
+                let row: Vec<char> = line.trim_end().chars().collect();
+                for &c in row.iter() {
+                    if c != ' ' && !legend.contains_key(&c) {
+                        return syntax_error(line_no, format!("`{}` is not in the legend", c));
+                    }
+                }
+                rows.push(row);
+                continue;
+            }
+
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            if trimmed == "---" {
+                in_layout = true;
+            } else if let Some(rest) = trimmed.strip_prefix("name:") {
+                name = rest.trim().to_string();
+            } else {
+                let mut chars = trimmed.chars();
+                let symbol = chars.next().unwrap();
+                let mut words = chars.as_str().split_whitespace();
+                let tile = match words.next() {
+                    Some(word) => match TileKind::from_name(word) {
+                        Some(kind) => kind,
+                        None => return syntax_error(line_no, format!("unknown tile kind `{}`", word)),
+                    },
+                    None => return syntax_error(line_no, format!("`{}` needs a tile kind", symbol)),
+                };
+                let spawn = words.next().map(String::from);
+                if let Some(extra) = words.next() {
+                    return syntax_error(line_no, format!("unexpected `{}`", extra));
+                }
+                legend.insert(symbol, LegendEntry { tile, spawn, lock: None });
+            }
+        }
+
+        // Trailing blank lines aren't part of the layout.
+        while rows.last().is_some_and(|row| row.is_empty()) {
+            rows.pop();
+        }
+        if rows.is_empty() {
+            return syntax_error(text.lines().count(), String::from("no layout after `---`"));
+        }
+
+        // Ragged rows are padded out with spaces, so every row is the same length.
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        for row in rows.iter_mut() {
+            row.resize(width, ' ');
+        }
+
+        Ok(Prefab { name, legend, rows })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Prefab, PrefabError> {
+        let mut text = String::new();
+        fs::File::open(path)?.read_to_string(&mut text)?;
+        Prefab::parse(&text)
+    }
+
+    /// Every `.txt` file in a directory.
+    pub fn load_dir<P: AsRef<Path>>(path: P) -> Result<Vec<Prefab>, PrefabError> {
+        let mut prefabs = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "txt") {
+                prefabs.push(Prefab::load(&path)?);
+            }
+        }
+        Ok(prefabs)
+    }
+
+    /// The prefabs shipped with the game.
+    pub fn builtin() -> Vec<Prefab> {
+        vec![
+            Prefab::parse(include_str!("../prefabs/shrine.txt")).unwrap(),
+            Prefab::parse(include_str!("../prefabs/pillars.txt")).unwrap(),
+            Prefab::parse(include_str!("../prefabs/lava_moat.txt")).unwrap(),
+        ]
+    }
+
+    pub fn width(&self) -> i32 {
+        self.rows.first().map_or(0, |row| row.len()) as i32
+    }
+
+    pub fn height(&self) -> i32 {
+        self.rows.len() as i32
+    }
+
+    /// What goes at the given position of the layout, if anything.
+    pub fn entry(&self, x: i32, y: i32) -> Option<&LegendEntry> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        self.rows.get(y as usize)
+            .and_then(|row| row.get(x as usize))
+            .and_then(|c| self.legend.get(c))
+    }
+
+    /// A copy turned 90 degrees clockwise.
+    pub fn rotated(&self) -> Prefab {
+        let (width, height) = (self.width() as usize, self.height() as usize);
+        let rows = (0..width)
+            .map(|x| (0..height).rev().map(|y| self.rows[y][x]).collect())
+            .collect();
+        Prefab { rows, ..self.clone() }
+    }
+
+    /// A copy flipped left to right.
+    pub fn mirrored(&self) -> Prefab {
+        let rows = self.rows.iter()
+            .map(|row| row.iter().rev().cloned().collect())
+            .collect();
+        Prefab { rows, ..self.clone() }
+    }
+
+    /// Tiles on the outer edge of the layout which can be walked through,
+    /// along with the direction pointing out of the prefab.
+    pub fn entrances(&self) -> Vec<((i32, i32), (i32, i32))> {
+        let (width, height) = (self.width(), self.height());
+        let mut entrances = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let walkable = self.entry(x, y).is_some_and(|e| {
+                    let tile = Tile::from_kind(e.tile);
+                    tile.walkable || tile.is_door()
+                });
+                if !walkable {
+                    continue;
+                }
+                let outward = if y == 0 { (0, -1) }
+                    else if y == height - 1 { (0, 1) }
+                    else if x == 0 { (-1, 0) }
+                    else if x == width - 1 { (1, 0) }
+                    else { continue };
+                entrances.push(((x, y), outward));
+            }
+        }
+        entrances
+    }
+
+    /// Copy the layout onto the map with its top left corner at `(x, y)`.
+    /// Returns the names and map positions of everything which should be spawned.
+    pub fn stamp(&self, map: &mut Map, x: i32, y: i32) -> Vec<(String, i32, i32)> {
+        let mut spawns = Vec::new();
+        for py in 0..self.height() {
+            for px in 0..self.width() {
+                if let Some(entry) = self.entry(px, py) {
+                    map.set(x + px, y + py, Tile { lock: entry.lock, ..Tile::from_kind(entry.tile) });
+                    if let Some(ref name) = entry.spawn {
+                        spawns.push((name.clone(), x + px, y + py));
+                    }
+                }
+            }
+        }
+        spawns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use map::{Map, TileKind};
+    use prefab::{Prefab, PrefabError};
+
+    const SMALL: &str = "name: small
+# Wall
+. Floor
+o Floor Orc
+---
+#.#
+#o
+";
+
+    fn layout(prefab: &Prefab) -> Vec<String> {
+        (0..prefab.height()).map(|y| {
+            (0..prefab.width()).map(|x| match prefab.entry(x, y).map(|e| e.tile) {
+                Some(TileKind::Wall) => '#',
+                Some(TileKind::Floor) => '.',
+                _ => ' ',
+            }).collect()
+        }).collect()
+    }
+
+    #[test]
+    fn parses_and_transforms() {
+        let prefab = Prefab::parse(SMALL).unwrap();
+        assert_eq!(prefab.name, "small");
+        assert_eq!(layout(&prefab), vec!["#.#", "#. "]);
+        assert_eq!(prefab.entry(1, 1).unwrap().spawn, Some(String::from("Orc")));
+
+        assert_eq!(layout(&prefab.rotated()), vec!["##", "..", " #"]);
+        assert_eq!(layout(&prefab.mirrored()), vec!["#.#", " .#"]);
+        assert_eq!(prefab.rotated().rotated().rotated().rotated(), prefab);
+        assert_eq!(prefab.entrances(), vec![((1, 0), (0, -1)), ((1, 1), (0, 1))]);
+    }
+
+    #[test]
+    fn stamps_onto_map() {
+        let prefab = Prefab::parse(SMALL).unwrap();
+        let mut map = Map::new(5, 5);
+        let spawns = prefab.stamp(&mut map, 2, 2);
+        assert_eq!(spawns, vec![(String::from("Orc"), 3, 3)]);
+        assert!(map.is_walkable(3, 2));
+        assert!(!map.is_walkable(2, 2));
+    }
+
+    #[test]
+    fn reports_bad_files() {
+        let error = |text: &str| match Prefab::parse(text) {
+            Err(PrefabError::Syntax { line, message }) => (line, message),
+            other => panic!("expected a syntax error, got {:?}", other),
+        };
+        assert_eq!(error("# Wall\n---\n#x#\n"), (3, String::from("`x` is not in the legend")));
+        assert_eq!(error("# Brick\n---\n#\n"), (1, String::from("unknown tile kind `Brick`")));
+        assert_eq!(error("# Wall\n").1, "no layout after `---`");
+    }
+
+    #[test]
+    fn builtin_prefabs_have_entrances() {
+        for prefab in Prefab::builtin() {
+            assert!(!prefab.entrances().is_empty(), "{} has no way in", prefab.name);
+        }
+    }
+}
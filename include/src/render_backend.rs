@@ -0,0 +1,261 @@
+extern crate pancurses;
+
+use entity::{Attributes, Color};
+use frame::{Cell, Frame};
+use palette::{ColorMode, Palette, PairCache};
+
+/// Anything that can put coloured glyphs on a grid of cells.
+/// The `Renderer` only talks to this trait, so it doesn't care whether the
+/// cells end up on a terminal or in memory.
+pub trait RenderBackend {
+    /// Width and height of the drawable area, in cells.
+    fn size(&self) -> (i32, i32);
+
+    /// Set the colours used by subsequent calls to `draw_glyph`.
+    fn set_color(&mut self, fg: &Color, bg: &Color);
+
+    /// Set the text attributes used by subsequent calls to `draw_glyph`.
+    fn set_attributes(&mut self, attrs: Attributes);
+
+    /// Draw a single glyph at the given cell using the current colours.
+    fn draw_glyph(&mut self, x: i32, y: i32, glyph: char);
+
+    /// Blank out the whole drawable area.
+    fn clear(&mut self);
+
+    /// Make everything drawn so far visible.
+    fn refresh(&mut self);
+
+    /// True if something already on screen may look different from how it was
+    /// drawn, so the next frame has to draw every cell again. Asking resets it.
+    fn take_invalidated(&mut self) -> bool {
+        false
+    }
+}
+
+/// Pancurses requires some colour intialization be called once per program
+/// in order to start up properly.
+pub fn init_colors() {
+    pancurses::start_color();
+    // This is required for being able to use -1 as the default color.
+    pancurses::use_default_colors();
+}
+
+/// Draws straight onto a pancurses window.
+pub struct PancursesBackend {
+    win: pancurses::Window,
+    palette: Palette,
+    pairs: PairCache,
+    pair: i16,
+    attrs: Attributes,
+    /// A colour pair in use was redefined since the renderer last asked.
+    invalidated: bool,
+}
+
+impl PancursesBackend {
+    /// Takes ownership of the window returned by `pancurses::initscr`.
+    /// Colours are set up here too, see `init_colors`.
+    pub fn new(win: pancurses::Window) -> Self {
+        init_colors();
+
+        let mode = ColorMode::from_capabilities(pancurses::COLORS(), pancurses::can_change_color());
+        info!("Using color mode {:?} ({} colors, {} pairs)",
+              mode, pancurses::COLORS(), pancurses::COLOR_PAIRS());
+
+        PancursesBackend {
+            win,
+            palette: Palette::new(mode, pancurses::COLORS()),
+            pairs: PairCache::new(pancurses::COLOR_PAIRS()),
+            pair: 0,
+            attrs: Attributes::NONE,
+            invalidated: false,
+        }
+    }
+
+    /// The underlying window, for things like reading input.
+    pub fn window(&self) -> &pancurses::Window {
+        &self.win
+    }
+
+    fn get_color(&mut self, color: &Color) -> i16 {
+        let resolved = self.palette.resolve(color);
+        if let Some((r, g, b)) = resolved.define {
+            pancurses::init_color(resolved.index, r, g, b);
+            info!("Defined color {} as {:?}", resolved.index, color);
+        }
+        resolved.index
+    }
+
+    /// Given a foreground and background color, we return the pancurses ID for the color pair.
+    /// If none exists, we create one, recycling an old pair if we have run out.
+    fn get_pair(&mut self, fg: &Color, bg: &Color) -> i16 {
+        let fg_index = self.get_color(fg);
+        let bg_index = self.get_color(bg);
+        let lookup = self.pairs.get(fg_index, bg_index);
+        if lookup.needs_init {
+            pancurses::init_pair(lookup.id, fg_index, bg_index);
+            info!("Inserted new color: {:?} {:?} as {}", fg, bg, lookup.id);
+        }
+        // Whatever was drawn with the old colours just changed to the new ones.
+        self.invalidated |= lookup.recycled;
+        lookup.id
+    }
+
+    /// `attrset` clears the colour pair as well, so always set both together.
+    fn apply_style(&self) {
+        let mut attrs = pancurses::A_NORMAL;
+        if self.attrs.contains(Attributes::BOLD) { attrs |= pancurses::A_BOLD; }
+        if self.attrs.contains(Attributes::DIM) { attrs |= pancurses::A_DIM; }
+        if self.attrs.contains(Attributes::UNDERLINE) { attrs |= pancurses::A_UNDERLINE; }
+        if self.attrs.contains(Attributes::REVERSE) { attrs |= pancurses::A_REVERSE; }
+        if self.attrs.contains(Attributes::BLINK) { attrs |= pancurses::A_BLINK; }
+        self.win.attrset(attrs);
+        // `COLOR_PAIR` only has room for 256 pairs, so use `color_set` instead.
+        self.win.color_set(self.pair);
+    }
+}
+
+impl RenderBackend for PancursesBackend {
+    fn size(&self) -> (i32, i32) {
+        (self.win.get_max_x(), self.win.get_max_y())
+    }
+
+    fn set_color(&mut self, fg: &Color, bg: &Color) {
+        self.pair = if *fg == Color::Default && *bg == Color::Default {
+            0
+        } else {
+            self.get_pair(fg, bg)
+        };
+        self.apply_style();
+    }
+
+    fn set_attributes(&mut self, attrs: Attributes) {
+        self.attrs = attrs;
+        self.apply_style();
+    }
+
+    fn draw_glyph(&mut self, x: i32, y: i32, glyph: char) {
+        self.win.mvaddstr(y, x, glyph.encode_utf8(&mut [0; 4]));
+    }
+
+    fn clear(&mut self) {
+        self.win.erase();
+    }
+
+    fn refresh(&mut self) {
+        self.win.refresh();
+    }
+
+    fn take_invalidated(&mut self) -> bool {
+        ::std::mem::replace(&mut self.invalidated, false)
+    }
+}
+
+/// Keeps everything in memory instead of drawing it.
+/// Useful in tests to check exactly what would have appeared on screen.
+pub struct BufferBackend {
+    frame: Frame,
+    fg: Color,
+    bg: Color,
+    attrs: Attributes,
+    /// Number of times `refresh` has been called.
+    pub refreshes: usize,
+    /// Number of glyphs drawn since creation.
+    pub draws: usize,
+    /// Reported by the next `take_invalidated`, standing in for a recycled colour pair.
+    pub invalidated: bool,
+}
+
+impl BufferBackend {
+    pub fn new(width: i32, height: i32) -> Self {
+        BufferBackend {
+            frame: Frame::new(width, height),
+            fg: Color::Default,
+            bg: Color::Default,
+            attrs: Attributes::NONE,
+            refreshes: 0,
+            draws: 0,
+            invalidated: false,
+        }
+    }
+
+    /// The cell at the given position, or `None` if it is off the buffer.
+    pub fn cell(&self, x: i32, y: i32) -> Option<&Cell> {
+        self.frame.get(x, y)
+    }
+
+    /// The glyphs of a single row, as a string.
+    pub fn row(&self, y: i32) -> String {
+        (0..self.frame.size().0)
+            .filter_map(|x| self.cell(x, y))
+            .map(|cell| cell.glyph)
+            .collect()
+    }
+
+    /// The glyphs of every row, joined with newlines.
+    pub fn contents(&self) -> String {
+        (0..self.frame.size().1)
+            .map(|y| self.row(y))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl RenderBackend for BufferBackend {
+    fn size(&self) -> (i32, i32) {
+        self.frame.size()
+    }
+
+    fn set_color(&mut self, fg: &Color, bg: &Color) {
+        self.fg = fg.clone();
+        self.bg = bg.clone();
+    }
+
+    fn set_attributes(&mut self, attrs: Attributes) {
+        self.attrs = attrs;
+    }
+
+    fn draw_glyph(&mut self, x: i32, y: i32, glyph: char) {
+        // Curses silently ignores out of range writes, and so does the frame.
+        self.draws += 1;
+        self.frame.set(x, y, Cell {
+            glyph,
+            fg: self.fg.clone(),
+            bg: self.bg.clone(),
+            attrs: self.attrs,
+        });
+    }
+
+    fn clear(&mut self) {
+        self.frame.clear();
+    }
+
+    fn refresh(&mut self) {
+        self.refreshes += 1;
+    }
+
+    fn take_invalidated(&mut self) -> bool {
+        ::std::mem::replace(&mut self.invalidated, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use render_backend::{BufferBackend, RenderBackend};
+    use entity::Color;
+
+    #[test]
+    fn buffer_records_glyphs_and_colors() {
+        let mut buf = BufferBackend::new(4, 2);
+        buf.set_color(&Color::Red, &Color::Default);
+        buf.draw_glyph(1, 1, '@');
+        buf.draw_glyph(9, 9, '!');
+
+        assert_eq!(buf.contents(), "    \n @  ");
+        assert_eq!(buf.cell(1, 1).unwrap().fg, Color::Red);
+        assert_eq!(buf.cell(9, 9), None);
+
+        buf.clear();
+        assert_eq!(buf.row(1), "    ");
+    }
+}
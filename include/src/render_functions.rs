@@ -1,97 +1,510 @@
-extern crate pancurses;
 extern crate specs;
 
-use entity::Color;
+use std::collections::VecDeque;
+
+use entity::{Color, Attributes};
 use map::Map;
-use component::{Position, BaseEntity};
-use std::collections::HashMap;
+use component::{Position, BaseEntity, Puppeted, Fighter, Hunger, HungerState, Viewshed};
+use render_backend::{self, RenderBackend};
+use frame::{Cell, Frame, FrameStats};
+use theme::Theme;
+use autotile::{AutoTiles, Charset};
+use status::{EffectKind, StatusEffects};
 use specs::{World, Join};
 
+/// Draws the world into a back buffer, then only sends the cells which
+/// changed since the last frame to the backend.
 pub struct Renderer {
-    pairs: HashMap<(Color, Color), u32>,
+    /// What we believe is currently on screen.
+    front: Frame,
+    /// The frame being built.
+    back: Frame,
+    last_stats: FrameStats,
+    theme: Theme,
+    charset: Charset,
+    /// Wall glyphs, kept until the map changes.
+    autotiles: Option<AutoTiles>,
+    /// Shots waiting to be shown, one after another.
+    projectiles: VecDeque<Projectile>,
+    /// Shown on the status bar after the player's health.
+    message: String,
 }
 
-impl Renderer {
-    pub fn new() -> Self {
-        Renderer { pairs: HashMap::new() }
+/// Something flying across the screen, drawn one tile further along each frame.
+struct Projectile {
+    path: Vec<(i32, i32)>,
+    step: usize,
+    glyph: char,
+}
+
+/// What goes in the status bar: the player's health, and how hungry they are
+/// if it is worth mentioning.
+fn status_line(world: &World) -> String {
+    let entities = world.entities();
+    let (puppets, fighters, hunger) = (world.read::<Puppeted>(), world.read::<Fighter>(), world.read::<Hunger>());
+    let player = match (&*entities, &puppets).join().next() {
+        Some((player, _)) => player,
+        None => return String::new(),
+    };
+    let mut parts = Vec::new();
+    if let Some(fighter) = fighters.get(player) {
+        parts.push(format!("HP {}/{}", fighter.hp, fighter.max_hp));
+    }
+    match hunger.get(player).map(|h| h.state()) {
+        None | Some(HungerState::Normal) => {},
+        Some(state) => parts.push(format!("{:?}", state)),
+    }
+    parts.join("  ")
+}
+
+/// A glyph pointing along the line from `from` to `to`.
+fn projectile_glyph(from: (i32, i32), to: (i32, i32)) -> char {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    if dy.abs() * 2 < dx.abs() {
+        '-'
+    } else if dx.abs() * 2 < dy.abs() {
+        '|'
+    } else if (dx > 0) == (dy > 0) {
+        '\\'
+    } else {
+        '/'
+    }
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Renderer::new()
     }
+}
 
+impl Renderer {
     /// Pancurses requires some intialization stuff be called once per program
     /// in order to start up properly.
-    /// There is probably a better way to do this.
+    #[deprecated(note = "`PancursesBackend::new` does this now; see `render_backend::init_colors`")]
     pub fn static_init() {
-        pancurses::start_color();
-        // This is required for being able to use -1 as the default color.
-        pancurses::use_default_colors();
+        render_backend::init_colors();
     }
 
-    /// Given a foreground and background color, we return the pancurses ID for the color pair.
-    /// If none exists, we create one.
-    fn get_pair(self: &mut Self, fg: Color, bg: Color) -> u32 {
-        let tup = (fg, bg);
+    pub fn new() -> Self {
+        Renderer {
+            front: Frame::default(),
+            back: Frame::default(),
+            last_stats: FrameStats::default(),
+            theme: Theme::default(),
+            charset: Charset::detect(),
+            autotiles: None,
+            projectiles: VecDeque::new(),
+            message: String::new(),
+        }
+    }
 
-        // This would be nice, but we need to perform some pancurses actions if this color pair doesn't exist
-        // self.pairs.entry(tup).or_insert(new_pair_id).clone()
+    /// Override the detected character set.
+    pub fn set_charset(&mut self, charset: Charset) {
+        self.charset = charset;
+        self.autotiles = None;
+    }
 
-        if self.pairs.contains_key(&tup) {
-            self.pairs.get(&tup).unwrap().clone()
-        }
-        else {
-            let new_pair_id = (self.pairs.len() + 1) as u32;
-            pancurses::init_pair(new_pair_id as i16, tup.0.clone() as i16, tup.1.clone() as i16);
-            info!("Inserted new color: {:?} {:?} as {}", tup.0, tup.1, new_pair_id);
-            self.pairs.insert(tup, new_pair_id);
-            new_pair_id as u32
-        }
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// Switch to a different theme. Takes effect on the next frame.
+    pub fn set_theme(&mut self, theme: Theme) {
+        info!("Switching to theme {}", theme.name);
+        self.theme = theme;
+    }
+
+    /// Show `message` on the status bar from the next frame on, until it is
+    /// replaced. An empty message shows nothing.
+    pub fn set_message(&mut self, message: String) {
+        self.message = message;
+    }
+
+    /// Show a projectile flying along `path`, once any launched before it have landed.
+    /// Each call to `render_all` moves it one tile the player can see, and the frame
+    /// after the last one lands is drawn without any.
+    pub fn launch(&mut self, path: Vec<(i32, i32)>) {
+        let glyph = match (path.first(), path.last()) {
+            (Some(&first), Some(&last)) if first != last => projectile_glyph(first, last),
+            _ => '*',
+        };
+        self.projectiles.push_back(Projectile { path, step: 0, glyph });
+    }
+
+    /// True until a frame has been drawn after every projectile finished flying.
+    pub fn is_animating(&self) -> bool {
+        !self.projectiles.is_empty()
+    }
+
+    /// Output generated by the most recent call to `render_all`.
+    pub fn last_frame_stats(&self) -> FrameStats {
+        self.last_stats
     }
 
     /// Render the map and all entities
-    pub fn render_all(self: &mut Self, win: &pancurses::Window, world: &World) {
-        let map = world.read_resource::<Map>();
-        // Render the map
-        for y in 0..map.data.len() {
-            for x in 0..map.data[0].len() {
-                let tile = map.data[y][x];
-                if !tile.transparent && !tile.walkable {
-                    win.mvprintw(y as i32, x as i32, "#");
+    pub fn render_all<B: RenderBackend + ?Sized>(&mut self, backend: &mut B, world: &World) {
+        let (width, height) = backend.size();
+        if self.back.size() != (width, height) {
+            self.back = Frame::new(width, height);
+        }
+        self.back.clear();
+
+        {
+            let map = world.read_resource::<Map>();
+            let autotiles = if self.theme.autotile {
+                let stale = self.autotiles.as_ref().is_none_or(|tiles| !tiles.is_current(&map));
+                if stale {
+                    debug!("Recomputing wall glyphs");
+                    self.autotiles = Some(AutoTiles::compute(&map, self.charset));
+                }
+                self.autotiles.as_ref()
+            } else {
+                None
+            };
+
+            // Projectiles only show where the player can see them, but the map is
+            // only cut down to what the player can see while they are blind.
+            // Without a player, everything is drawn.
+            let (entities, puppets, viewsheds, effects) = (world.entities(), world.read::<Puppeted>(),
+                                                           world.read::<Viewshed>(), world.read::<StatusEffects>());
+            let player = (&*entities, &puppets, &viewsheds).join().next();
+            let in_view = |x: i32, y: i32| player.is_none_or(|(_, _, viewshed)| viewshed.visible.contains(&(x, y)));
+            let blind = player.is_some_and(|(ent, _, _)| {
+                effects.get(ent).is_some_and(|e| e.has(EffectKind::Blindness))
+            });
+            let seen = |x: i32, y: i32| !blind || in_view(x, y);
+
+            // Render the map
+            for (x, y, tile) in map.iter().filter(|&(x, y, _)| seen(x, y)) {
+                let style = self.theme.style(tile.kind);
+                let glyph = autotiles
+                    .and_then(|tiles| tiles.glyph(x, y))
+                    .unwrap_or(style.glyph);
+                self.back.set(x, y, Cell {
+                    glyph,
+                    fg: style.fg,
+                    bg: style.bg,
+                    attrs: style.attrs | tile.attrs,
+                });
+            }
+
+            // Render all entities
+            let positions = world.read::<Position>();
+            let base_ent = world.read::<BaseEntity>();
+            for (base, pos) in (&base_ent, &positions).join().filter(|&(_, pos)| seen(pos.x, pos.y)) {
+                self.back.set(pos.x, pos.y, Cell {
+                    glyph: base.glyph,
+                    fg: base.fg.clone(),
+                    bg: base.bg.clone(),
+                    attrs: base.attrs,
+                });
+            }
+
+            // The status bar goes on the first row under the map, if there is room.
+            let status_y = map.height();
+            if status_y < height {
+                let mut line = status_line(world);
+                if !line.is_empty() && !self.message.is_empty() {
+                    line.push_str("  ");
                 }
-                else {
-                    win.mvprintw(y as i32, x as i32, ".");
+                line.push_str(&self.message);
+                for (x, glyph) in line.chars().enumerate() {
+                    self.back.set(x as i32, status_y, Cell {
+                        glyph,
+                        fg: Color::Default,
+                        bg: Color::Default,
+                        attrs: Attributes::NONE,
+                    });
                 }
             }
+
+            self.advance_projectiles(in_view);
+        }
+
+        self.present(backend);
+    }
+
+    /// Draw the next tile the player can see along the path of the first
+    /// projectile still in flight, dropping any which have landed.
+    fn advance_projectiles<F: Fn(i32, i32) -> bool>(&mut self, in_view: F) {
+        while let Some(projectile) = self.projectiles.front_mut() {
+            let next = projectile.path[projectile.step..].iter().position(|&(x, y)| in_view(x, y));
+            match next {
+                Some(skipped) => {
+                    let (x, y) = projectile.path[projectile.step + skipped];
+                    self.back.set(x, y, Cell {
+                        glyph: projectile.glyph,
+                        fg: Color::Default,
+                        bg: Color::Default,
+                        attrs: Attributes::BOLD,
+                    });
+                    projectile.step += skipped + 1;
+                    return;
+                },
+                None => {
+                    self.projectiles.pop_front();
+                },
+            }
         }
+    }
 
-        // Render all entities
-        let positions = world.read::<Position>();
-        let baseEnt = world.read::<BaseEntity>();
-        for entity in world.entities().join() {
-            if let Some(base) = baseEnt.get(entity) {
-                if let Some(pos) = positions.get(entity) {
-                    win.attrset(pancurses::COLOR_PAIR(self.get_pair(base.fg.clone(), base.bg.clone()).into()));
-                    win.mvprintw(pos.y, pos.x, &base.glyph.to_string());
+    /// Send everything that differs between the back and front buffers to the backend,
+    /// then swap them.
+    fn present<B: RenderBackend + ?Sized>(&mut self, backend: &mut B) {
+        let mut stats = FrameStats::default();
+        {
+            let mut current_style: Option<(&Color, &Color, Attributes)> = None;
+            let mut cursor: Option<(i32, i32)> = None;
+            for (x, y, cell) in self.back.diff(&self.front) {
+                if current_style != Some((&cell.fg, &cell.bg, cell.attrs)) {
+                    backend.set_color(&cell.fg, &cell.bg);
+                    backend.set_attributes(cell.attrs);
+                    current_style = Some((&cell.fg, &cell.bg, cell.attrs));
+                    stats.bytes_written += FrameStats::style_change_bytes(cell.attrs);
+                }
+                // The terminal cursor advances after each glyph, so neighbouring
+                // cells on the same row don't need an explicit move.
+                if cursor != Some((x, y)) {
+                    stats.bytes_written += FrameStats::cursor_move_bytes(x, y);
                 }
+                backend.draw_glyph(x, y, cell.glyph);
+                cursor = Some((x + 1, y));
+                stats.cells_written += 1;
+                stats.bytes_written += cell.glyph.len_utf8();
             }
         }
-        win.attrset(pancurses::COLOR_PAIR(0));
+        backend.set_color(&Color::Default, &Color::Default);
+        backend.set_attributes(Attributes::NONE);
+        backend.refresh();
+
+        debug!("Frame wrote {} cells ({} bytes)", stats.cells_written, stats.bytes_written);
+        self.last_stats = stats;
+        ::std::mem::swap(&mut self.front, &mut self.back);
+        if backend.take_invalidated() {
+            debug!("Screen invalidated, redrawing everything next frame");
+            self.forget_front();
+        }
+    }
+
+    /// Forget what is on screen, so the next frame draws everything.
+    fn forget_front(&mut self) {
+        let (width, height) = self.front.size();
+        self.front = Frame::new(width, height);
+    }
+
+    pub fn clear_all<B: RenderBackend + ?Sized>(&mut self, backend: &mut B) {
+        backend.clear();
+        backend.refresh();
+        // The screen is blank now, so the next frame has to draw everything.
+        self.forget_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use specs::World;
+    use component::{register_all, Position, BaseEntity, Puppeted, Viewshed};
+    use entity::{Color, Attributes};
+    use map::{Map, Tile, TileKind};
+    use theme::Theme;
+    use autotile::Charset;
+    use render_backend::BufferBackend;
+    use render_functions::Renderer;
+    use status::{EffectKind, StatusEffects};
+
+    #[test]
+    fn renders_map_and_entities() {
+        let mut world = World::new();
+        register_all(&mut world);
+
+        let mut map = Map::new(4, 3);
+        map.set(1, 1, Tile::from_kind(TileKind::Floor));
+        map.set(2, 1, Tile::from_kind(TileKind::Floor));
+        map.get_mut(1, 1).unwrap().attrs = Attributes::DIM;
+        world.add_resource(map);
+
+        world.create_entity()
+            .with(Position { x: 2, y: 1 })
+            .with(BaseEntity {
+                    fg: Color::Red,
+                    bg: Color::Default,
+                    attrs: Attributes::BOLD | Attributes::BLINK,
+                    glyph: '@',
+                    blocks: true,
+                    name: String::from("Player"),
+                })
+            .build();
+
+        let mut buf = BufferBackend::new(5, 3);
+        let mut renderer = Renderer::new();
+        renderer.set_charset(Charset::Unicode);
+        renderer.render_all(&mut buf, &world);
+
+        assert_eq!(buf.contents(), "#### \n#.@# \n#### ");
+        assert_eq!(buf.cell(2, 1).unwrap().fg, Color::Red);
+        assert_eq!(buf.cell(1, 1).unwrap().fg, Color::Default);
+        assert_eq!(buf.cell(1, 1).unwrap().attrs, Attributes::DIM);
+        assert!(buf.cell(2, 1).unwrap().attrs.contains(Attributes::BLINK));
+        assert!(!buf.cell(2, 1).unwrap().attrs.contains(Attributes::DIM));
+        assert_eq!(buf.cell(0, 0).unwrap().attrs, Attributes::NONE);
+        assert_eq!(buf.refreshes, 1);
+
+        renderer.clear_all(&mut buf);
+        assert_eq!(buf.row(1), "     ");
+
+        renderer.set_theme(Theme::builtin().remove(1));
+        renderer.render_all(&mut buf, &world);
+        assert_eq!(buf.contents(), "┌──┐ \n│·@│ \n└──┘ ");
+    }
+
+    #[test]
+    fn only_changed_cells_are_redrawn() {
+        let mut world = World::new();
+        register_all(&mut world);
+
+        let mut map = Map::new(4, 3);
+        for x in 1..3 {
+            map.set(x, 1, Tile::from_kind(TileKind::Floor));
+        }
+        world.add_resource(map);
+
+        let player = world.create_entity()
+            .with(Position { x: 1, y: 1 })
+            .with(BaseEntity {
+                    fg: Color::Red,
+                    bg: Color::Default,
+                    attrs: Attributes::NONE,
+                    glyph: '@',
+                    blocks: true,
+                    name: String::from("Player"),
+                })
+            .build();
+
+        let mut buf = BufferBackend::new(4, 3);
+        let mut renderer = Renderer::new();
+        renderer.render_all(&mut buf, &world);
+        assert_eq!(renderer.last_frame_stats().cells_written, 12);
+
+        // Nothing changed, so nothing should be sent.
+        renderer.render_all(&mut buf, &world);
+        assert_eq!(renderer.last_frame_stats().cells_written, 0);
+        assert_eq!(renderer.last_frame_stats().bytes_written, 0);
+
+        // Moving the player touches exactly two cells.
+        world.write::<Position>().get_mut(player).unwrap().x = 2;
+        let draws_before = buf.draws;
+        renderer.render_all(&mut buf, &world);
+        assert_eq!(renderer.last_frame_stats().cells_written, 2);
+        assert_eq!(buf.draws - draws_before, 2);
+        assert_eq!(buf.contents(), "####\n#.@#\n####");
+
+        // After a clear, the blank cells don't need redrawing but everything else does.
+        renderer.clear_all(&mut buf);
+        renderer.render_all(&mut buf, &world);
+        assert_eq!(renderer.last_frame_stats().cells_written, 12);
+
+        // Same again when the backend says the screen can't be trusted, as
+        // when a colour pair is recycled.
+        buf.invalidated = true;
+        renderer.render_all(&mut buf, &world);
+        assert_eq!(renderer.last_frame_stats().cells_written, 0);
+        renderer.render_all(&mut buf, &world);
+        assert_eq!(renderer.last_frame_stats().cells_written, 12);
+    }
+
+    #[test]
+    fn projectiles_fly_one_tile_per_frame() {
+        let mut world = World::new();
+        register_all(&mut world);
+        let mut map = Map::new(5, 1);
+        for x in 0..5 {
+            map.set(x, 0, Tile::from_kind(TileKind::Floor));
+        }
+        world.add_resource(map);
 
-        // for ent in entities.iter() {
-        //     self.draw_entity(win, ent);
-        // }
+        let mut buf = BufferBackend::new(5, 1);
+        let mut renderer = Renderer::new();
+        renderer.launch(vec![(1, 0), (2, 0), (3, 0)]);
+        let mut frames = Vec::new();
+        while renderer.is_animating() {
+            renderer.render_all(&mut buf, &world);
+            frames.push(buf.contents());
+        }
+        assert_eq!(frames, vec![".-...", "..-..", "...-.", "....."]);
+
+        renderer.launch(vec![(1, 0)]);
+        renderer.render_all(&mut buf, &world);
+        assert_eq!(buf.contents(), ".*...");
+        renderer.render_all(&mut buf, &world);
+
+        // Shots launched together fly one after the other.
+        renderer.launch(vec![(1, 0), (2, 0)]);
+        renderer.launch(vec![(3, 0)]);
+        let mut frames = Vec::new();
+        while renderer.is_animating() {
+            renderer.render_all(&mut buf, &world);
+            frames.push(buf.contents());
+        }
+        assert_eq!(frames, vec![".-...", "..-..", "...*.", "....."]);
     }
 
-    pub fn clear_all(self: &mut Self, win: &pancurses::Window, world: &World) {
-        // for ent in entities.iter() {
-        //     self.clear_entity(win, ent);
-        // }
+    #[test]
+    fn projectiles_are_only_shown_where_the_player_can_see() {
+        let mut world = World::new();
+        register_all(&mut world);
+        let mut map = Map::new(5, 1);
+        for x in 0..5 {
+            map.set(x, 0, Tile::from_kind(TileKind::Floor));
+        }
+        world.add_resource(map);
+        let mut viewshed = Viewshed::new(1);
+        viewshed.visible = [(0, 0), (1, 0), (3, 0)].iter().cloned().collect::<HashSet<_>>();
+        world.create_entity().with(Puppeted).with(viewshed).with(Position { x: 0, y: 0 }).build();
+
+        let mut buf = BufferBackend::new(5, 1);
+        let mut renderer = Renderer::new();
+        renderer.launch(vec![(1, 0), (2, 0), (3, 0), (4, 0)]);
+        renderer.launch(vec![(4, 0)]);
+        let mut frames = Vec::new();
+        while renderer.is_animating() {
+            renderer.render_all(&mut buf, &world);
+            frames.push(buf.contents());
+        }
+        assert_eq!(frames, vec![".-...", "...-.", "....."]);
     }
 
-    // pub fn draw_entity(self: &mut Self, win: &pancurses::Window, ent: &Entity) {
-    //     win.attrset(pancurses::COLOR_PAIR(self.get_pair(ent.fg.clone(), ent.bg.clone())));
-    //     win.mvprintw(ent.y, ent.x, &ent.glyph.to_string());
-    // }
+    #[test]
+    fn blind_players_only_see_their_viewshed() {
+        let mut world = World::new();
+        register_all(&mut world);
+        let mut map = Map::new(5, 1);
+        for x in 0..5 {
+            map.set(x, 0, Tile::from_kind(TileKind::Floor));
+        }
+        world.add_resource(map);
+        let glyph = |glyph| BaseEntity {
+            glyph, fg: Color::Default, bg: Color::Default, attrs: Attributes::NONE, blocks: true,
+            name: String::from("Thing"),
+        };
+        world.create_entity().with(Position { x: 4, y: 0 }).with(glyph('o')).build();
+        let mut viewshed = Viewshed::new(1);
+        viewshed.visible = [(0, 0), (1, 0), (2, 0)].iter().cloned().collect::<HashSet<_>>();
+
+        let player = world.create_entity()
+            .with(Puppeted).with(viewshed).with(Position { x: 1, y: 0 }).with(glyph('@'))
+            .build();
 
-    // pub fn clear_entity(self: &mut Self, win: &pancurses::Window, ent: &Entity) {
-    //     win.attrset(pancurses::COLOR_PAIR(0));
-    //     win.mvprintw(ent.y, ent.x, " ");
-    // }
+        let mut buf = BufferBackend::new(5, 1);
+        let mut renderer = Renderer::new();
+        renderer.render_all(&mut buf, &world);
+        assert_eq!(buf.contents(), ".@..o");
+
+        let mut effects = StatusEffects::new();
+        effects.add(EffectKind::Blindness, 3);
+        world.write::<StatusEffects>().insert(player, effects);
+        renderer.render_all(&mut buf, &world);
+        assert_eq!(buf.contents(), ".@.  ");
+    }
 }
@@ -0,0 +1,99 @@
+extern crate serde_json;
+
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use event::Event;
+
+/// Everything needed to play a session again: since map generation is seeded,
+/// the same seed and the same events always end up in the same place.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Recording {
+    pub seed: usize,
+    pub events: Vec<Event>,
+}
+
+/// How fast to play a recording back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pace {
+    /// As fast as possible.
+    Instant,
+    /// Wait this long before each event.
+    Delay(Duration),
+    /// Wait for a key press before each event.
+    Step,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplayError::Io(ref e) => write!(f, "could not read recording: {}", e),
+            ReplayError::Parse(ref e) => write!(f, "could not parse recording: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> Self {
+        ReplayError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ReplayError {
+    fn from(e: serde_json::Error) -> Self {
+        ReplayError::Parse(e)
+    }
+}
+
+impl Recording {
+    pub fn new(seed: usize) -> Self {
+        Recording { seed, events: Vec::new() }
+    }
+
+    pub fn from_json(json: &str) -> Result<Recording, ReplayError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Recording, ReplayError> {
+        let mut json = String::new();
+        File::open(path)?.read_to_string(&mut json)?;
+        Recording::from_json(&json)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ReplayError> {
+        File::create(path)?.write_all(self.to_json().as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use event::Event;
+    use replay::{Recording, ReplayError};
+
+    #[test]
+    fn round_trips_through_json() {
+        let mut recording = Recording::new(42);
+        recording.events = vec![Event::Movement((1, 0)), Event::PickUp, Event::Quit];
+        assert_eq!(Recording::from_json(&recording.to_json()).unwrap(), recording);
+
+        match Recording::from_json(r#"{ "seed": 1, "events": ["Dance"] }"#) {
+            Err(ReplayError::Parse(_)) => {},
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use specs::Entity;
+
+/// Which entities are on each tile of the map, so systems can ask
+/// "what is here?" without scanning every entity.
+///
+/// This only knows about entities; whether the map itself lets you walk
+/// somewhere is still up to `Map`.
+pub struct SpatialIndex {
+    width: i32,
+    height: i32,
+    /// Number of blocking entities on each tile.
+    blockers: Vec<u32>,
+    entities: Vec<Vec<Entity>>,
+}
+
+impl SpatialIndex {
+    pub fn new(width: i32, height: i32) -> Self {
+        let (width, height) = (width.max(0), height.max(0));
+        let size = (width * height) as usize;
+        SpatialIndex {
+            width,
+            height,
+            blockers: vec![0; size],
+            entities: vec![Vec::new(); size],
+        }
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            None
+        } else {
+            Some((y * self.width + x) as usize)
+        }
+    }
+
+    /// Forget about every entity.
+    pub fn clear(&mut self) {
+        for count in self.blockers.iter_mut() {
+            *count = 0;
+        }
+        for list in self.entities.iter_mut() {
+            list.clear();
+        }
+    }
+
+    /// Record an entity as being on a tile. Positions off the map are ignored.
+    pub fn insert(&mut self, entity: Entity, x: i32, y: i32, blocks: bool) {
+        if let Some(i) = self.index(x, y) {
+            self.entities[i].push(entity);
+            if blocks {
+                self.blockers[i] += 1;
+            }
+        }
+    }
+
+    /// Forget an entity was on a tile.
+    pub fn remove(&mut self, entity: Entity, x: i32, y: i32, blocks: bool) {
+        if let Some(i) = self.index(x, y) {
+            let before = self.entities[i].len();
+            self.entities[i].retain(|&e| e != entity);
+            if blocks && self.entities[i].len() != before {
+                self.blockers[i] -= 1;
+            }
+        }
+    }
+
+    /// Update the index after an entity has moved.
+    pub fn move_entity(&mut self, entity: Entity, from: (i32, i32), to: (i32, i32), blocks: bool) {
+        self.remove(entity, from.0, from.1, blocks);
+        self.insert(entity, to.0, to.1, blocks);
+    }
+
+    /// True if a blocking entity is on the tile.
+    pub fn is_blocked(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).is_some_and(|i| self.blockers[i] > 0)
+    }
+
+    /// Every entity on the tile, in the order they arrived.
+    pub fn entities_at(&self, x: i32, y: i32) -> &[Entity] {
+        match self.index(x, y) {
+            Some(i) => &self.entities[i],
+            None => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::World;
+    use spatial::SpatialIndex;
+
+    #[test]
+    fn tracks_entities_and_blockers() {
+        let mut world = World::new();
+        let orc = world.create_entity().build();
+        let potion = world.create_entity().build();
+
+        let mut index = SpatialIndex::new(4, 4);
+        index.insert(orc, 1, 1, true);
+        index.insert(potion, 1, 1, false);
+        index.insert(potion, 9, 9, false);
+
+        assert!(index.is_blocked(1, 1));
+        assert_eq!(index.entities_at(1, 1), &[orc, potion]);
+        assert!(index.entities_at(9, 9).is_empty());
+
+        index.move_entity(orc, (1, 1), (2, 1), true);
+        assert!(!index.is_blocked(1, 1));
+        assert!(index.is_blocked(2, 1));
+        assert_eq!(index.entities_at(1, 1), &[potion]);
+
+        // Removing something which isn't there doesn't disturb the count.
+        index.remove(orc, 1, 1, true);
+        assert!(index.is_blocked(2, 1));
+
+        index.clear();
+        assert!(!index.is_blocked(2, 1));
+        assert!(index.entities_at(1, 1).is_empty());
+    }
+}
@@ -0,0 +1,264 @@
+extern crate rand;
+
+use spawn::rand::Rng;
+
+use specs::{Entity, World};
+
+use entity::{Color, Attributes};
+use component::{BaseEntity, Position, MoveDelta, Blocking, Item, Fighter, Healing, Ranged, Ammo, Food};
+use faction::Faction;
+
+/// Names and positions of things to create on the map.
+pub type SpawnList = Vec<(String, i32, i32)>;
+
+/// Create something by name at the given position.
+/// Returns `None` if we don't know how to make it.
+pub fn spawn(world: &mut World, name: &str, x: i32, y: i32) -> Option<Entity> {
+    let monster = |world: &mut World, glyph: char, fg: Color, attrs: Attributes, fighter: Fighter| {
+        world.create_entity()
+            .with(Position { x, y })
+            .with(Blocking)
+            .with(MoveDelta { dx: 0, dy: 0 })
+            .with(fighter)
+            .with(Faction::Monsters)
+            .with(BaseEntity { glyph, fg, bg: Color::Default, attrs, blocks: true, name: String::from(name) })
+            .build()
+    };
+    let item = |world: &mut World, glyph: char, fg: Color| {
+        world.create_entity()
+            .with(Position { x, y })
+            .with(Item)
+            .with(BaseEntity { glyph, fg, bg: Color::Default, attrs: Attributes::NONE, blocks: false,
+                               name: String::from(name) })
+            .build()
+    };
+
+    match name {
+        "Goblin" => Some(monster(world, 'g', Color::Yellow, Attributes::NONE, Fighter::new(6, 2, 0))),
+        "Orc" => Some(monster(world, 'o', Color::Green, Attributes::NONE, Fighter::new(10, 3, 0))),
+        "Troll" => Some(monster(world, 'T', Color::Green, Attributes::BOLD, Fighter::new(16, 4, 1))),
+        "Mysterious Glyph" => Some(world.create_entity()
+            .with(Position { x, y })
+            .with(Blocking)
+            .with(MoveDelta { dx: 0, dy: 0 })
+            .with(Faction::Strangers)
+            .with(BaseEntity { glyph: '$', fg: Color::Blue, bg: Color::Default, attrs: Attributes::NONE,
+                               blocks: true, name: String::from(name) })
+            .build()),
+        "Healing Potion" => {
+            let potion = item(world, '!', Color::Magenta);
+            world.write::<Healing>().insert(potion, Healing { amount: 8 });
+            Some(potion)
+        },
+        "Ration" => {
+            let ration = item(world, '%', Color::Yellow);
+            world.write::<Food>().insert(ration, Food { amount: 400 });
+            Some(ration)
+        },
+        "Dagger" => {
+            let dagger = item(world, ')', Color::Cyan);
+            world.write::<Ranged>().insert(dagger, Ranged { range: 4, damage: 4, thrown: true });
+            Some(dagger)
+        },
+        "Shortbow" => {
+            let bow = item(world, '}', Color::Yellow);
+            world.write::<Ranged>().insert(bow, Ranged { range: 8, damage: 5, thrown: false });
+            Some(bow)
+        },
+        "Arrows" => {
+            let arrows = item(world, '/', Color::Yellow);
+            world.write::<Ammo>().insert(arrows, Ammo { count: 10 });
+            Some(arrows)
+        },
+        _ => {
+            warn!("Don't know how to spawn {}", name);
+            None
+        },
+    }
+}
+
+/// A number which changes as you go deeper. Each entry is a depth and the value
+/// from that depth on, until the next entry takes over. Above the first entry
+/// the value is 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByDepth(pub Vec<(u32, u32)>);
+
+impl ByDepth {
+    pub fn at(&self, depth: u32) -> u32 {
+        self.0.iter()
+            .filter(|&&(from, _)| from <= depth)
+            .max_by_key(|&&(from, _)| from)
+            .map_or(0, |&(_, value)| value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnKind {
+    Monster,
+    Item,
+}
+
+/// Something which can turn up in a room, and how likely it is at each depth.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpawnEntry {
+    pub name: String,
+    pub kind: SpawnKind,
+    pub weight: ByDepth,
+}
+
+/// What gets put in rooms, and how much of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpawnTable {
+    pub entries: Vec<SpawnEntry>,
+    /// The most monsters a single room can have.
+    pub max_monsters: ByDepth,
+    /// The most items a single room can have.
+    pub max_items: ByDepth,
+}
+
+impl Default for SpawnTable {
+    fn default() -> Self {
+        let entry = |name: &str, kind, weight: &[(u32, u32)]| {
+            SpawnEntry { name: String::from(name), kind, weight: ByDepth(weight.to_vec()) }
+        };
+        SpawnTable {
+            entries: vec![
+                entry("Goblin", SpawnKind::Monster, &[(1, 40), (4, 10)]),
+                entry("Orc", SpawnKind::Monster, &[(1, 80)]),
+                entry("Troll", SpawnKind::Monster, &[(3, 15), (5, 30), (7, 60)]),
+                entry("Healing Potion", SpawnKind::Item, &[(1, 35)]),
+                entry("Ration", SpawnKind::Item, &[(1, 35)]),
+                entry("Dagger", SpawnKind::Item, &[(2, 10)]),
+                entry("Shortbow", SpawnKind::Item, &[(3, 8)]),
+                entry("Arrows", SpawnKind::Item, &[(3, 12)]),
+            ],
+            max_monsters: ByDepth(vec![(1, 2), (4, 3), (6, 5)]),
+            max_items: ByDepth(vec![(1, 1), (4, 2)]),
+        }
+    }
+}
+
+impl SpawnTable {
+    /// The most things of a kind a single room can have at a depth.
+    pub fn room_cap(&self, kind: SpawnKind, depth: u32) -> u32 {
+        match kind {
+            SpawnKind::Monster => self.max_monsters.at(depth),
+            SpawnKind::Item => self.max_items.at(depth),
+        }
+    }
+
+    fn weights(&self, kind: SpawnKind, depth: u32) -> Vec<(&str, u32)> {
+        self.entries.iter()
+            .filter(|entry| entry.kind == kind)
+            .map(|entry| (entry.name.as_str(), entry.weight.at(depth)))
+            .filter(|&(_, weight)| weight > 0)
+            .collect()
+    }
+
+    /// Pick one thing of a kind at random, weighted for the depth.
+    /// Returns `None` if nothing of that kind can turn up there.
+    pub fn pick<R: Rng>(&self, kind: SpawnKind, depth: u32, rng: &mut R) -> Option<&str> {
+        let weights = self.weights(kind, depth);
+        let total: u32 = weights.iter().map(|&(_, weight)| weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = rng.gen_range(0, total);
+        for (name, weight) in weights {
+            if roll < weight {
+                return Some(name);
+            }
+            roll -= weight;
+        }
+        None
+    }
+
+    /// How many things of a kind to put in one room: anywhere from none up to the cap.
+    pub fn roll_count<R: Rng>(&self, kind: SpawnKind, depth: u32, rng: &mut R) -> u32 {
+        rng.gen_range(0, self.room_cap(kind, depth) + 1)
+    }
+
+    /// The chance of each `pick` of a kind at a depth coming up with each name.
+    pub fn distribution(&self, kind: SpawnKind, depth: u32) -> Vec<(&str, f64)> {
+        let weights = self.weights(kind, depth);
+        let total: u32 = weights.iter().map(|&(_, weight)| weight).sum();
+        weights.into_iter()
+            .map(|(name, weight)| (name, f64::from(weight) / f64::from(total)))
+            .collect()
+    }
+
+    /// How many of each thing of a kind an average room has at a depth.
+    /// Handy for balancing.
+    pub fn expected_per_room(&self, kind: SpawnKind, depth: u32) -> Vec<(&str, f64)> {
+        // `roll_count` is uniform between 0 and the cap.
+        let count = f64::from(self.room_cap(kind, depth)) / 2.0;
+        self.distribution(kind, depth).into_iter()
+            .map(|(name, chance)| (name, chance * count))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::World;
+    use component::register_all;
+    use map_utils::seeded_rng;
+    use spawn::{spawn, ByDepth, SpawnKind, SpawnTable};
+
+    #[test]
+    fn values_change_with_depth() {
+        let value = ByDepth(vec![(5, 30), (2, 10)]);
+        assert_eq!(value.at(1), 0);
+        assert_eq!(value.at(2), 10);
+        assert_eq!(value.at(4), 10);
+        assert_eq!(value.at(9), 30);
+
+        let table = SpawnTable::default();
+        assert!(table.room_cap(SpawnKind::Monster, 1) < table.room_cap(SpawnKind::Monster, 6));
+    }
+
+    #[test]
+    fn picks_match_distribution() {
+        let table = SpawnTable::default();
+        let depth = 5;
+        let distribution = table.distribution(SpawnKind::Monster, depth);
+        assert_eq!(distribution.iter().map(|&(name, _)| name).collect::<Vec<_>>(),
+                   vec!["Goblin", "Orc", "Troll"]);
+        assert!((distribution.iter().map(|&(_, chance)| chance).sum::<f64>() - 1.0).abs() < 1e-9);
+
+        let rolls = 10000;
+        for &(name, chance) in distribution.iter() {
+            let mut rng = seeded_rng(0);
+            let hits = (0..rolls)
+                .filter(|_| table.pick(SpawnKind::Monster, depth, &mut rng) == Some(name))
+                .count();
+            let seen = hits as f64 / f64::from(rolls);
+            assert!((seen - chance).abs() < 0.02, "{}: expected {}, got {}", name, chance, seen);
+        }
+
+        // Nothing is deep enough yet.
+        assert!(table.distribution(SpawnKind::Item, 0).is_empty());
+        assert_eq!(table.pick(SpawnKind::Item, 0, &mut seeded_rng(0)), None);
+    }
+
+    #[test]
+    fn expected_counts_follow_caps() {
+        let table = SpawnTable::default();
+        let expected = table.expected_per_room(SpawnKind::Item, 1);
+        assert_eq!(expected, vec![("Healing Potion", 0.25), ("Ration", 0.25)]);
+        let total: f64 = table.expected_per_room(SpawnKind::Monster, 6).iter().map(|&(_, n)| n).sum();
+        assert!((total - 2.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn everything_in_the_table_can_be_spawned() {
+        let mut world = World::new();
+        register_all(&mut world);
+
+        for entry in SpawnTable::default().entries {
+            assert!(spawn(&mut world, &entry.name, 1, 1).is_some(), "{}", entry.name);
+        }
+        assert!(spawn(&mut world, "Mysterious Glyph", 1, 1).is_some());
+        assert!(spawn(&mut world, "Unicorn", 1, 1).is_none());
+    }
+}
@@ -0,0 +1,192 @@
+extern crate rand;
+
+use status::rand::Rng;
+
+use specs::{Component, VecStorage};
+
+/// Something temporary affecting an entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EffectKind {
+    /// Loses a hit point every turn.
+    Poison,
+    /// Gains a hit point every turn.
+    Regeneration,
+    /// Stumbles in a random direction when trying to move.
+    Confusion,
+    /// Actions take half as long.
+    Haste,
+    /// Actions take twice as long.
+    Slow,
+    /// Can only see the tiles next to it.
+    Blindness,
+}
+
+impl EffectKind {
+    /// The effect which cancels this one out, if any.
+    fn opposite(self) -> Option<EffectKind> {
+        match self {
+            EffectKind::Haste => Some(EffectKind::Slow),
+            EffectKind::Slow => Some(EffectKind::Haste),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusEffect {
+    pub kind: EffectKind,
+    /// Turns left before it wears off.
+    pub turns: u32,
+}
+
+/// Every effect on an entity, at most one of each kind.
+///
+/// Adding an effect which is already there follows these rules:
+/// poison and regeneration stack, so their durations add up; anything else
+/// just lasts for whichever is longer. Haste and slow cancel each other
+/// out turn for turn.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatusEffects {
+    effects: Vec<StatusEffect>,
+}
+impl Component for StatusEffects {
+    type Storage = VecStorage<Self>;
+}
+
+impl StatusEffects {
+    pub fn new() -> Self {
+        StatusEffects::default()
+    }
+
+    pub fn add(&mut self, kind: EffectKind, turns: u32) {
+        let mut turns = turns;
+        if let Some(opposite) = kind.opposite() {
+            if let Some(i) = self.effects.iter().position(|e| e.kind == opposite) {
+                let cancelled = turns.min(self.effects[i].turns);
+                self.effects[i].turns -= cancelled;
+                turns -= cancelled;
+                if self.effects[i].turns == 0 {
+                    self.effects.remove(i);
+                }
+            }
+        }
+        if turns == 0 {
+            return;
+        }
+        match self.effects.iter_mut().find(|e| e.kind == kind) {
+            Some(existing) => existing.turns = match kind {
+                EffectKind::Poison | EffectKind::Regeneration => existing.turns + turns,
+                _ => existing.turns.max(turns),
+            },
+            None => self.effects.push(StatusEffect { kind, turns }),
+        }
+    }
+
+    pub fn has(&self, kind: EffectKind) -> bool {
+        self.effects.iter().any(|e| e.kind == kind)
+    }
+
+    /// Turns left on an effect, or 0 if it isn't there.
+    pub fn remaining(&self, kind: EffectKind) -> u32 {
+        self.effects.iter().find(|e| e.kind == kind).map_or(0, |e| e.turns)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &StatusEffect> {
+        self.effects.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// One turn passes. Returns the effects which wore off.
+    pub fn tick(&mut self) -> Vec<EffectKind> {
+        for effect in self.effects.iter_mut() {
+            effect.turns -= 1;
+        }
+        let expired = self.effects.iter().filter(|e| e.turns == 0).map(|e| e.kind).collect();
+        self.effects.retain(|e| e.turns > 0);
+        expired
+    }
+
+    /// How long an action which normally takes `cost` takes with these effects.
+    pub fn action_cost(&self, cost: u32) -> u32 {
+        if self.has(EffectKind::Haste) {
+            cost / 2
+        } else if self.has(EffectKind::Slow) {
+            cost * 2
+        } else {
+            cost
+        }
+    }
+
+    /// Where a step of `(dx, dy)` actually goes. Confused entities pick a
+    /// random direction instead, which may well be the one they wanted.
+    pub fn stumble<R: Rng>(&self, (dx, dy): (i32, i32), rng: &mut R) -> (i32, i32) {
+        if !self.has(EffectKind::Confusion) || (dx, dy) == (0, 0) {
+            return (dx, dy);
+        }
+        loop {
+            let step = (rng.gen_range(-1, 2), rng.gen_range(-1, 2));
+            if step != (0, 0) {
+                return step;
+            }
+        }
+    }
+
+    /// How far can be seen with these effects, if it would normally be `range`.
+    pub fn sight_range(&self, range: i32) -> i32 {
+        if self.has(EffectKind::Blindness) { range.min(1) } else { range }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use map_utils::seeded_rng;
+    use status::{EffectKind, StatusEffects};
+
+    #[test]
+    fn stacking_rules() {
+        let mut effects = StatusEffects::new();
+        effects.add(EffectKind::Poison, 3);
+        effects.add(EffectKind::Poison, 2);
+        assert_eq!(effects.remaining(EffectKind::Poison), 5);
+
+        effects.add(EffectKind::Confusion, 4);
+        effects.add(EffectKind::Confusion, 2);
+        assert_eq!(effects.remaining(EffectKind::Confusion), 4);
+
+        effects.add(EffectKind::Slow, 3);
+        effects.add(EffectKind::Haste, 5);
+        assert!(!effects.has(EffectKind::Slow));
+        assert_eq!(effects.remaining(EffectKind::Haste), 2);
+        effects.add(EffectKind::Slow, 1);
+        assert_eq!(effects.remaining(EffectKind::Haste), 1);
+        assert_eq!(effects.action_cost(100), 50);
+    }
+
+    #[test]
+    fn effects_wear_off() {
+        let mut effects = StatusEffects::new();
+        effects.add(EffectKind::Blindness, 1);
+        effects.add(EffectKind::Regeneration, 2);
+        assert_eq!(effects.sight_range(8), 1);
+        assert_eq!(effects.tick(), vec![EffectKind::Blindness]);
+        assert_eq!(effects.sight_range(8), 8);
+        assert_eq!(effects.tick(), vec![EffectKind::Regeneration]);
+        assert!(effects.is_empty());
+    }
+
+    #[test]
+    fn confusion_scrambles_steps() {
+        let mut effects = StatusEffects::new();
+        let mut rng = seeded_rng(3);
+        assert_eq!(effects.stumble((1, 0), &mut rng), (1, 0));
+        effects.add(EffectKind::Confusion, 10);
+        let steps: Vec<_> = (0..50).map(|_| effects.stumble((1, 0), &mut rng)).collect();
+        assert!(steps.iter().all(|&(dx, dy)| dx.abs() <= 1 && dy.abs() <= 1 && (dx, dy) != (0, 0)));
+        assert!(steps.iter().any(|&step| step != (1, 0)));
+        // Standing still is never scrambled.
+        assert_eq!(effects.stumble((0, 0), &mut rng), (0, 0));
+    }
+}
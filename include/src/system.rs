@@ -1,9 +1,16 @@
 extern crate pancurses;
 
-use specs::{ReadStorage, WriteStorage, System, Join, Fetch, Entities};
-use component::{MoveDelta, Position, BaseEntity, Puppeted, Blocking};
-use event::{Event, EventQueue};
+use specs::{ReadStorage, WriteStorage, System, Join, Fetch, FetchMut, Entities};
+use action::{Action, ActionContext};
+use bus::{EventBus, EventKind, GameEvent, SubscriberId};
+use component::{MoveDelta, Position, BaseEntity, Puppeted, Blocking, Item, Key, Inventory, Fighter, Healing,
+                Viewshed, Ranged, Ammo, Food, Hunger, HungerState};
+use faction::{Faction, Relationships};
+use fov::field_of_view;
+use game::GameRng;
 use map::Map;
+use spatial::SpatialIndex;
+use status::{EffectKind, StatusEffects};
 
 pub struct UpdatePos;
 
@@ -13,7 +20,7 @@ impl <'a> System<'a> for UpdatePos {
                         WriteStorage<'a, Position> );
 
     fn run(&mut self, (mut delta, mut pos): Self::SystemData) {
-        for (mut delta, mut pos) in (&mut delta, &mut pos).join() {
+        for (delta, pos) in (&mut delta, &mut pos).join() {
             pos.x += delta.dx;
             pos.y += delta.dy;
             delta.dx = 0;
@@ -46,79 +53,389 @@ impl <'a> System<'a> for UpdatePos {
 //     }
 // }
 
-pub struct EventSystem;
+pub struct SpatialIndexSystem;
 
-impl <'a> System<'a> for EventSystem {
+/// System which rebuilds the `SpatialIndex` from scratch, so anything added,
+/// removed or moved outside of the other systems is picked up.
+impl <'a> System<'a> for SpatialIndexSystem {
     type SystemData = ( Entities<'a>,
-                        Fetch<'a, EventQueue>,
                         Fetch<'a, Map>,
+                        FetchMut<'a, SpatialIndex>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, Blocking> );
+
+    fn run(&mut self, (entities, map, mut index, pos, blocking): Self::SystemData) {
+        if index.size() != (map.width(), map.height()) {
+            *index = SpatialIndex::new(map.width(), map.height());
+        }
+        index.clear();
+        for (ent, pos) in (&*entities, &pos).join() {
+            index.insert(ent, pos.x, pos.y, blocking.get(ent).is_some());
+        }
+    }
+}
+
+/// System which turns the player's input into actions for every puppeted
+/// entity, and announces how each one went.
+pub struct EventSystem {
+    input: SubscriberId,
+}
+
+impl EventSystem {
+    pub fn new(bus: &mut EventBus) -> Self {
+        EventSystem { input: bus.subscribe(&[EventKind::Input]) }
+    }
+}
+
+impl <'a> System<'a> for EventSystem {
+    type SystemData = ( Entities<'a>,
+                        FetchMut<'a, EventBus>,
+                        FetchMut<'a, Map>,
+                        FetchMut<'a, SpatialIndex>,
                         ReadStorage<'a, Puppeted>,
                         WriteStorage<'a, Position>,
                         ReadStorage<'a, Blocking>,
-                        ReadStorage<'a, BaseEntity>);
+                        ReadStorage<'a, BaseEntity>,
+                        ReadStorage<'a, Item>,
+                        ReadStorage<'a, Key>,
+                        WriteStorage<'a, Inventory>,
+                        WriteStorage<'a, Fighter>,
+                        ReadStorage<'a, Healing>,
+                        ReadStorage<'a, StatusEffects>,
+                        ReadStorage<'a, Ranged>,
+                        WriteStorage<'a, Ammo>,
+                        ReadStorage<'a, Food>,
+                        WriteStorage<'a, Hunger>,
+                        ReadStorage<'a, Faction>,
+                        Fetch<'a, Relationships>,
+                        FetchMut<'a, GameRng>);
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, events, map, puppet, mut pos, blocking, baseent) = data;
-        for event in events.0.iter() {
+        let (entities, mut bus, mut map, mut index, puppet, mut pos, blocking, baseent,
+             items, keys, mut inventory, mut fighters, healing, effects, ranged, mut ammo,
+             food, mut hunger, factions, relationships, mut rng) = data;
+
+        // Iterate through every moving entity and store it for later,
+        // since we can't move them while iterating
+        let moving_ents: Vec<_> = (&puppet, &*entities, &pos).join()
+            .map(|(_, ent, _)| ent)
+            .collect();
+
+        let events = bus.read(self.input);
+        let mut ctx = ActionContext {
+            entities: &entities,
+            map: &mut map,
+            index: &mut index,
+            positions: &mut pos,
+            blocking: &blocking,
+            names: &baseent,
+            items: &items,
+            keys: &keys,
+            inventories: &mut inventory,
+            fighters: &mut fighters,
+            healing: &healing,
+            effects: &effects,
+            ranged: &ranged,
+            ammo: &mut ammo,
+            food: &food,
+            hunger: &mut hunger,
+            factions: &factions,
+            relationships: &relationships,
+            bus: &mut bus,
+        };
+
+        for event in events {
             info!("Detected event: {:?}", event);
-            match event {
-                // If a movement has occured...
-                &Event::Movement((dx, dy)) => {
-                    let mut blocking_ents = Vec::new();
-                    let mut moving_ents = Vec::new();
-
-                    // Iterate through every blocking entity and store it for later
-                    for (_, ent, posa, _) in (&blocking, &*entities, &pos, &baseent).join() {
-                        blocking_ents.push(ent);
-                    }
+            let action = match event {
+                GameEvent::Input(ref input) => Action::from_event(input),
+                _ => None,
+            };
+            let action = match action {
+                Some(action) => action,
+                None => continue,
+            };
+            for &ent in moving_ents.iter() {
+                let result = ctx.perform_confused(ent, action, &mut rng.0);
+                ctx.bus.emit(GameEvent::Acted(result));
+            }
+        }
+    }
+}
 
-                    // Iterate through every moving entity and store it for later
-                    for (_, ent, posa) in (&puppet, &*entities, &pos).join() {
-                        moving_ents.push(ent);
+/// System which clears away anything which has died.
+pub struct DeathSystem {
+    deaths: SubscriberId,
+}
+
+impl DeathSystem {
+    pub fn new(bus: &mut EventBus) -> Self {
+        DeathSystem { deaths: bus.subscribe(&[EventKind::Died]) }
+    }
+}
+
+impl <'a> System<'a> for DeathSystem {
+    type SystemData = ( Entities<'a>,
+                        FetchMut<'a, EventBus>,
+                        FetchMut<'a, SpatialIndex>,
+                        WriteStorage<'a, Position>,
+                        ReadStorage<'a, Blocking>,
+                        ReadStorage<'a, BaseEntity> );
+
+    fn run(&mut self, (entities, mut bus, mut index, mut pos, blocking, baseent): Self::SystemData) {
+        for event in bus.read(self.deaths) {
+            if let GameEvent::Died { entity } = event {
+                info!("{} dies", baseent.get(entity).map_or("Something", |b| &b.name));
+                if let Some(p) = pos.remove(entity) {
+                    index.remove(entity, p.x, p.y, blocking.get(entity).is_some());
+                }
+                let _ = entities.delete(entity);
+            }
+        }
+    }
+}
+
+/// System which makes one turn pass for everything with status effects.
+/// `Game` runs it once for every `TURN_COST` the player spends, so haste and
+/// slow change how many actions the player gets before effects tick.
+pub struct StatusSystem;
+
+impl <'a> System<'a> for StatusSystem {
+    type SystemData = ( Entities<'a>,
+                        FetchMut<'a, EventBus>,
+                        WriteStorage<'a, StatusEffects>,
+                        WriteStorage<'a, Fighter> );
+
+    fn run(&mut self, (entities, mut bus, mut effects, mut fighters): Self::SystemData) {
+        for (ent, effects) in (&*entities, &mut effects).join() {
+            if let Some(fighter) = fighters.get_mut(ent) {
+                if effects.has(EffectKind::Poison) && fighter.hp > 0 {
+                    fighter.hp -= 1;
+                    bus.emit(GameEvent::Damaged { target: ent, source: ent, amount: 1 });
+                    if fighter.hp <= 0 {
+                        bus.emit(GameEvent::Died { entity: ent });
                     }
+                }
+                if effects.has(EffectKind::Regeneration) && fighter.hp > 0 {
+                    fighter.hp = (fighter.hp + 1).min(fighter.max_hp);
+                }
+            }
+            for effect in effects.tick() {
+                bus.emit(GameEvent::EffectEnded { entity: ent, effect });
+            }
+        }
+    }
+}
+
+/// System which makes everything with `Hunger` one turn hungrier, and
+/// hurts anything which is starving. Run once a turn, like `StatusSystem`.
+pub struct HungerSystem;
+
+impl <'a> System<'a> for HungerSystem {
+    type SystemData = ( Entities<'a>,
+                        FetchMut<'a, EventBus>,
+                        WriteStorage<'a, Hunger>,
+                        WriteStorage<'a, Fighter> );
 
-                    // For every moving entity...
-                    for &ent in moving_ents.iter() {
-                        let can_move = {
-                            // Figure out where the mover wants to move
-                            let posa = pos.get(ent).unwrap();
-
-                            info!("This mover lives at {:?}", posa);
-                            let (new_x, new_y) = (posa.x + dx, posa.y + dy);
-
-                            // Check that the map isn't blocking it
-                            let &tile = map.at(new_x, new_y);
-                            if tile.walkable {
-                                // Check an entity isn't blocking it
-                                let mut blocked_by_ent = false;
-                                for &block in blocking_ents.iter() {
-                                    info!("Checking: {:?} and {:?}", ent, block);
-                                    if let Some(block_pos) = pos.get(block) {
-                                        if block_pos.x == new_x && block_pos.y == new_y {
-                                            let name = &baseent.get(block).unwrap().name;
-                                            info!("Collision! {}", name);
-                                            blocked_by_ent = true;
-                                            break;
-                                        }
-                                    }
-                                }
-                                !blocked_by_ent
-                            }
-                            else { false }
-                        };
-
-                        if can_move {
-                            // Have to do the mutable borrow here, otherwise we would have a
-                            // mutable + immutable borrow at the same time when checking the
-                            // blocking entity's position
-                            let posa = pos.get_mut(ent).unwrap();
-                            posa.x += dx;
-                            posa.y += dy;
-                        }
+    fn run(&mut self, (entities, mut bus, mut hunger, mut fighters): Self::SystemData) {
+        for (ent, hunger) in (&*entities, &mut hunger).join() {
+            let before = hunger.state();
+            hunger.food = (hunger.food - 1).max(0);
+            if hunger.state() != before {
+                bus.emit(GameEvent::HungerChanged { entity: ent, state: hunger.state() });
+            }
+            if hunger.state() != HungerState::Starving {
+                continue;
+            }
+            if let Some(fighter) = fighters.get_mut(ent) {
+                if fighter.hp > 0 {
+                    fighter.hp -= 1;
+                    bus.emit(GameEvent::Damaged { target: ent, source: ent, amount: 1 });
+                    if fighter.hp <= 0 {
+                        bus.emit(GameEvent::Died { entity: ent });
                     }
-                },
-                _ => {}
+                }
             }
         }
     }
 }
+
+/// System which works out what everything with a `Viewshed` can see.
+pub struct VisibilitySystem;
+
+impl <'a> System<'a> for VisibilitySystem {
+    type SystemData = ( Entities<'a>,
+                        Fetch<'a, Map>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, StatusEffects>,
+                        WriteStorage<'a, Viewshed> );
+
+    fn run(&mut self, (entities, map, pos, effects, mut viewsheds): Self::SystemData) {
+        for (ent, pos, viewshed) in (&*entities, &pos, &mut viewsheds).join() {
+            // Blind things can still feel what is next to them.
+            let range = effects.get(ent).map_or(viewshed.range, |e| e.sight_range(viewshed.range));
+            viewshed.visible = field_of_view(&map, (pos.x, pos.y), range);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{Entity, RunNow, World};
+    use action::ActionError;
+    use bus::{EventBus, EventKind, GameEvent};
+    use component::{Position, BaseEntity, Puppeted, Blocking, Item, Key, Inventory, Fighter, Hunger, HungerState};
+    use entity::{Attributes, Color};
+    use event::Event;
+    use map::{Map, TileKind};
+    use status::{EffectKind, StatusEffects};
+    use system::{EventSystem, HungerSystem, SpatialIndexSystem, StatusSystem};
+    use testing::level;
+
+    fn mob(world: &mut World, x: i32, y: i32, name: &str) -> Entity {
+        world.create_entity()
+            .with(Position { x, y })
+            .with(Blocking)
+            .with(BaseEntity {
+                    fg: Color::Default,
+                    bg: Color::Default,
+                    attrs: Attributes::NONE,
+                    glyph: '?',
+                    blocks: true,
+                    name: String::from(name),
+                })
+            .build()
+    }
+
+    fn player(world: &mut World, x: i32, y: i32) -> Entity {
+        let player = mob(world, x, y, "Player");
+        world.write::<Puppeted>().insert(player, Puppeted);
+        world.write::<Inventory>().insert(player, Inventory::default());
+        player
+    }
+
+    /// Run one event through the systems and report where `ent` ends up.
+    fn step(world: &mut World, ent: Entity, event: Event) -> (i32, i32) {
+        let mut system = EventSystem::new(&mut world.write_resource::<EventBus>());
+        world.write_resource::<EventBus>().emit(GameEvent::Input(event));
+        SpatialIndexSystem.run_now(&world.res);
+        system.run_now(&world.res);
+        world.write_resource::<EventBus>().unsubscribe(system.input);
+        let pos = world.read::<Position>();
+        let p = pos.get(ent).unwrap();
+        (p.x, p.y)
+    }
+
+    #[test]
+    fn movers_respect_map_and_blockers() {
+        let mut world = level(".@.o\n####");
+        let player = player(&mut world, 1, 0);
+
+        assert_eq!(step(&mut world, player, Event::Movement((1, 0))), (2, 0));
+        // Blocked by the orc
+        assert_eq!(step(&mut world, player, Event::Movement((1, 0))), (2, 0));
+        // Blocked by the wall
+        assert_eq!(step(&mut world, player, Event::Movement((0, 1))), (2, 0));
+        assert_eq!(step(&mut world, player, Event::Movement((-2, 0))), (0, 0));
+        // Off the edge of the map
+        assert_eq!(step(&mut world, player, Event::Movement((-1, 0))), (0, 0));
+        assert_eq!(step(&mut world, player, Event::Movement((0, -1))), (0, 0));
+    }
+
+    #[test]
+    fn bumping_opens_doors_and_keys_unlock_them() {
+        let mut world = level(".+.L.\n#####");
+        let player = player(&mut world, 0, 0);
+        let key = world.create_entity()
+            .with(Position { x: 2, y: 0 })
+            .with(Item)
+            .with(Key { id: 4 })
+            .build();
+
+        // The first bump opens the door, the second walks through it.
+        assert_eq!(step(&mut world, player, Event::Movement((1, 0))), (0, 0));
+        assert!(world.read_resource::<Map>().get(1, 0).unwrap().transparent);
+        assert_eq!(step(&mut world, player, Event::Movement((1, 0))), (1, 0));
+        assert_eq!(step(&mut world, player, Event::Movement((1, 0))), (2, 0));
+
+        // Locked without the key
+        let results = world.write_resource::<EventBus>().subscribe(&[EventKind::Acted]);
+        assert_eq!(step(&mut world, player, Event::Movement((1, 0))), (2, 0));
+        match world.write_resource::<EventBus>().read(results).pop() {
+            Some(GameEvent::Acted(result)) => assert_eq!(result.outcome, Err(ActionError::Locked)),
+            other => panic!("expected an action result, got {:?}", other),
+        }
+        assert_eq!(world.read_resource::<Map>().get(3, 0).unwrap().kind, TileKind::DoorClosed);
+
+        step(&mut world, player, Event::PickUp);
+        assert!(world.read::<Position>().get(key).is_none());
+        assert_eq!(world.read::<Inventory>().get(player).unwrap().items, vec![key]);
+
+        assert_eq!(step(&mut world, player, Event::Movement((1, 0))), (2, 0));
+        assert_eq!(step(&mut world, player, Event::Movement((1, 0))), (3, 0));
+
+        // Can't close a door on yourself, but can once you've stepped out.
+        step(&mut world, player, Event::CloseDoors);
+        assert_eq!(world.read_resource::<Map>().get(3, 0).unwrap().kind, TileKind::DoorOpen);
+        assert_eq!(step(&mut world, player, Event::Movement((1, 0))), (4, 0));
+        step(&mut world, player, Event::CloseDoors);
+        let door = *world.read_resource::<Map>().get(3, 0).unwrap();
+        assert_eq!(door.kind, TileKind::DoorClosed);
+        assert!(!door.transparent);
+        assert_eq!(door.lock, None);
+    }
+
+    #[test]
+    fn poison_hurts_every_turn_until_it_wears_off() {
+        let mut world = level("o.");
+        let orc = world.create_entity().build();
+        world.write::<Fighter>().insert(orc, Fighter::new(3, 0, 0));
+        let mut effects = StatusEffects::new();
+        effects.add(EffectKind::Poison, 2);
+        effects.add(EffectKind::Confusion, 1);
+        world.write::<StatusEffects>().insert(orc, effects);
+        let watcher = world.write_resource::<EventBus>().subscribe(&[EventKind::Died, EventKind::EffectEnded]);
+
+        StatusSystem.run_now(&world.res);
+        StatusSystem.run_now(&world.res);
+        StatusSystem.run_now(&world.res);
+        assert_eq!(world.read::<Fighter>().get(orc).unwrap().hp, 1);
+        assert_eq!(world.write_resource::<EventBus>().read(watcher), vec![
+            GameEvent::EffectEnded { entity: orc, effect: EffectKind::Confusion },
+            GameEvent::EffectEnded { entity: orc, effect: EffectKind::Poison },
+        ]);
+
+        world.write::<StatusEffects>().get_mut(orc).unwrap().add(EffectKind::Poison, 5);
+        StatusSystem.run_now(&world.res);
+        assert_eq!(world.write_resource::<EventBus>().read(watcher), vec![GameEvent::Died { entity: orc }]);
+    }
+
+    #[test]
+    fn hunger_runs_down_and_starvation_hurts() {
+        let mut world = level(".");
+        let hero = world.create_entity()
+            .with(Hunger { food: 102, max_food: 1000 })
+            .with(Fighter::new(2, 0, 0))
+            .build();
+        let watcher = world.write_resource::<EventBus>().subscribe(&[EventKind::HungerChanged, EventKind::Died]);
+
+        for _ in 0..102 {
+            HungerSystem.run_now(&world.res);
+        }
+        assert_eq!(world.read::<Hunger>().get(hero).unwrap().food, 0);
+        assert_eq!(world.read::<Fighter>().get(hero).unwrap().hp, 1);
+        HungerSystem.run_now(&world.res);
+        HungerSystem.run_now(&world.res);
+        assert_eq!(world.write_resource::<EventBus>().read(watcher), vec![
+            GameEvent::HungerChanged { entity: hero, state: HungerState::Weak },
+            GameEvent::HungerChanged { entity: hero, state: HungerState::Starving },
+            GameEvent::Died { entity: hero },
+        ]);
+
+        // Small stomachs go through every state too.
+        let states: Vec<_> = [81, 31, 11, 1, 0].iter()
+            .map(|&food| Hunger { food, max_food: 100 }.state())
+            .collect();
+        assert_eq!(states, vec![HungerState::Satiated, HungerState::Normal, HungerState::Hungry, HungerState::Weak,
+                                HungerState::Starving]);
+    }
+}
@@ -0,0 +1,30 @@
+//! Fixtures shared by the unit tests.
+
+use specs::World;
+
+use bus::EventBus;
+use component::register_all;
+use faction::Relationships;
+use game::GameRng;
+use import::{parse_map, Legend};
+use map::TileKind;
+use map_utils::seeded_rng;
+use spatial::SpatialIndex;
+
+/// A world holding the map drawn in `layout`, with everything on it spawned
+/// and the resources the systems and actions need.
+/// `L` is a door locked with key 4.
+pub fn level(layout: &str) -> World {
+    let mut world = World::new();
+    register_all(&mut world);
+
+    let legend = Legend::default().locked('L', TileKind::DoorClosed, 4);
+    let imported = parse_map(layout, &legend).unwrap();
+    imported.spawn_into(&mut world);
+    world.add_resource(SpatialIndex::new(0, 0));
+    world.add_resource(EventBus::new());
+    world.add_resource(GameRng(seeded_rng(0)));
+    world.add_resource(Relationships::default());
+    world.add_resource(imported.map);
+    world
+}
@@ -0,0 +1,133 @@
+extern crate serde_json;
+
+use entity::{Attributes, Color};
+use map::TileKind;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+/// How a single kind of tile is drawn.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileStyle {
+    pub glyph: char,
+    pub fg: Color,
+    pub bg: Color,
+    #[serde(default)]
+    pub attrs: Attributes,
+}
+
+impl Default for TileStyle {
+    /// Used for any kind of tile a theme forgot about, so it stands out.
+    fn default() -> Self {
+        TileStyle { glyph: '?', fg: Color::Magenta, bg: Color::Default, attrs: Attributes::NONE }
+    }
+}
+
+/// A named set of tile styles, loaded from JSON.
+/// See the `themes` directory for examples.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    pub tiles: HashMap<TileKind, TileStyle>,
+    /// Draw walls with box drawing characters picked from their neighbours,
+    /// instead of the glyph for `Wall`.
+    #[serde(default)]
+    pub autotile: bool,
+}
+
+#[derive(Debug)]
+pub enum ThemeError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ThemeError::Io(ref e) => write!(f, "could not read theme: {}", e),
+            ThemeError::Parse(ref e) => write!(f, "could not parse theme: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for ThemeError {
+    fn from(e: io::Error) -> Self {
+        ThemeError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ThemeError {
+    fn from(e: serde_json::Error) -> Self {
+        ThemeError::Parse(e)
+    }
+}
+
+impl Theme {
+    pub fn from_json(json: &str) -> Result<Theme, ThemeError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Theme, ThemeError> {
+        let mut json = String::new();
+        File::open(path)?.read_to_string(&mut json)?;
+        Theme::from_json(&json)
+    }
+
+    /// The themes shipped with the game, plain ASCII first.
+    pub fn builtin() -> Vec<Theme> {
+        vec![
+            Theme::from_json(include_str!("../themes/ascii.json")).unwrap(),
+            Theme::from_json(include_str!("../themes/unicode.json")).unwrap(),
+        ]
+    }
+
+    /// How to draw the given kind of tile.
+    pub fn style(&self, kind: TileKind) -> TileStyle {
+        self.tiles.get(&kind).cloned().unwrap_or_default()
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::builtin().remove(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use entity::{Attributes, Color};
+    use map::TileKind;
+    use theme::Theme;
+
+    #[test]
+    fn builtin_themes_cover_every_tile_kind() {
+        for theme in Theme::builtin() {
+            for kind in TileKind::all() {
+                assert!(theme.tiles.contains_key(kind), "{} is missing {:?}", theme.name, kind);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_styles() {
+        let theme = Theme::from_json(r#"{
+            "name": "test",
+            "tiles": {
+                "Wall": { "glyph": "│", "fg": { "Rgb": { "r": 1, "g": 2, "b": 3 } }, "bg": "Black",
+                          "attrs": ["bold", "reverse"] }
+            }
+        }"#).unwrap();
+
+        let wall = theme.style(TileKind::Wall);
+        assert_eq!(wall.glyph, '│');
+        assert_eq!(wall.fg, Color::rgb(1, 2, 3));
+        assert_eq!(wall.attrs, Attributes::BOLD | Attributes::REVERSE);
+        assert_eq!(theme.style(TileKind::Floor).glyph, '?');
+
+        assert!(Theme::from_json(r##"{ "name": "bad", "tiles": {
+            "Wall": { "glyph": "#", "fg": "Red", "bg": "Red", "attrs": ["sparkly"] } } }"##).is_err());
+    }
+}
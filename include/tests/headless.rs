@@ -0,0 +1,243 @@
+extern crate rlr;
+extern crate specs;
+
+use rlr::action::Action;
+use rlr::bus::GameEvent;
+use rlr::component::{BaseEntity, Fighter, Hunger, Position, Viewshed};
+use rlr::driver::{run, HeadlessDriver, Recorder};
+use rlr::event::Event;
+use rlr::faction::{Faction, Relation, Relationships};
+use rlr::game::Game;
+use rlr::import::{parse_map, Legend};
+use rlr::map::{Map, TileKind};
+use rlr::replay::Recording;
+use rlr::status::{EffectKind, StatusEffects};
+use specs::Join;
+
+fn walk(moves: &str) -> Vec<Event> {
+    moves.chars().map(|c| match c {
+        'h' => Event::Movement((-1, 0)),
+        'j' => Event::Movement((0, 1)),
+        'k' => Event::Movement((0, -1)),
+        'l' => Event::Movement((1, 0)),
+        _ => panic!("no move for {}", c),
+    }).collect()
+}
+
+#[test]
+fn scripted_game_on_a_hand_made_map() {
+    let imported = parse_map("\
+#######
+#@.+.o#
+#######", &Legend::default()).unwrap();
+    let mut game = Game::from_imported(imported);
+    let mut driver = HeadlessDriver::new(walk("lllll"), 7, 3);
+    run(&mut game, &mut driver);
+
+    // One bump to open the door, then stopped by the orc.
+    assert_eq!(game.player_position(), Some((4, 1)));
+    assert_eq!(game.world().read_resource::<Map>().get(3, 1).unwrap().kind, TileKind::DoorOpen);
+    assert_eq!(driver.screen(), "#######\n#..'@o#\n#######");
+    assert_eq!(driver.remaining(), 0);
+    assert_eq!(game.turns(), 5);
+    // The last bump was an attack.
+    assert!(game.last_events().iter().any(|event| matches!(event, GameEvent::Damaged { .. })));
+}
+
+#[test]
+fn quitting_stops_the_script() {
+    let mut game = Game::new(1);
+    let mut events = walk("ll");
+    events.push(Event::Quit);
+    events.extend(walk("jj"));
+    let mut driver = HeadlessDriver::new(events, 32, 32);
+    run(&mut game, &mut driver);
+
+    assert!(!game.is_running());
+    assert_eq!(game.turns(), 2);
+    assert_eq!(driver.remaining(), 2);
+}
+
+#[test]
+fn same_seed_and_input_give_the_same_game() {
+    let play = || {
+        let mut game = Game::new(99);
+        let mut driver = HeadlessDriver::new(walk("lllljjjjhhkkllllllll"), 32, 32);
+        run(&mut game, &mut driver);
+        (game.player_position(), driver.screen())
+    };
+    assert_eq!(play(), play());
+}
+
+#[test]
+fn recordings_replay_the_same_game() {
+    let mut game = Game::new(7);
+    let mut recorder = Recorder::new(HeadlessDriver::new(walk("jjjlllkkhhhllll"), 32, 32), 7);
+    run(&mut game, &mut recorder);
+    let json = recorder.recording().to_json();
+
+    let recording = Recording::from_json(&json).unwrap();
+    assert_eq!(recording.seed, 7);
+    assert_eq!(recording.events.len(), 15);
+    let mut replayed = Game::new(recording.seed);
+    let mut driver = HeadlessDriver::new(recording.events, 32, 32);
+    run(&mut replayed, &mut driver);
+
+    assert_eq!(replayed.player_position(), game.player_position());
+    assert_eq!(replayed.turns(), game.turns());
+}
+
+#[test]
+fn haste_gives_extra_actions_before_effects_wear_off() {
+    let mut game = Game::from_imported(parse_map("#####\n#@..#\n#####", &Legend::default()).unwrap());
+    let player = game.player();
+    {
+        let mut effects = game.world().write::<StatusEffects>();
+        let effects = effects.get_mut(player).unwrap();
+        effects.add(EffectKind::Haste, 2);
+        effects.add(EffectKind::Blindness, 2);
+    }
+    let remaining = |game: &Game, kind| game.world().read::<StatusEffects>().get(player).unwrap().remaining(kind);
+    let seen = |game: &Game| game.world().read::<Viewshed>().get(player).unwrap().visible.len();
+
+    // Two hasted actions make one turn for everything else.
+    game.step(Event::Wait);
+    assert_eq!((game.time(), remaining(&game, EffectKind::Haste)), (50, 2));
+    assert_eq!(seen(&game), 9);
+    game.step(Event::Wait);
+    assert_eq!((game.time(), remaining(&game, EffectKind::Haste)), (100, 1));
+
+    game.step(Event::Wait);
+    game.step(Event::Wait);
+    assert_eq!(game.time(), 200);
+    assert!(game.last_events().contains(&GameEvent::EffectEnded { entity: player, effect: EffectKind::Haste }));
+    assert!(seen(&game) > 9);
+    game.step(Event::Wait);
+    assert_eq!((game.time(), game.turns()), (300, 5));
+}
+
+#[test]
+fn shots_are_animated_between_steps() {
+    let imported = parse_map("\
+#######
+#@}/.o#
+#######", &Legend::default()).unwrap();
+    let mut game = Game::from_imported(imported);
+    let events = vec![Event::Movement((1, 0)), Event::PickUp, Event::Movement((1, 0)), Event::PickUp,
+                      Event::Fire, Event::Wait];
+    let mut driver = HeadlessDriver::new(events, 7, 3);
+    run(&mut game, &mut driver);
+
+    // One frame before each event and one at the end, plus two more
+    // while the arrow flies to the orc.
+    assert_eq!(driver.frames(), 9);
+    assert_eq!(driver.screen(), "#######\n#..@.o#\n#######");
+    assert_eq!(game.turns(), 6);
+}
+
+#[test]
+fn shots_are_only_animated_once() {
+    let mut game = Game::from_imported(parse_map("#######\n#@}/.o#\n#######", &Legend::default()).unwrap());
+    let events = vec![Event::Movement((1, 0)), Event::PickUp, Event::Movement((1, 0)), Event::PickUp,
+                      Event::Fire, Event::NextTheme, Event::NextTheme];
+    let mut driver = HeadlessDriver::new(events, 7, 3);
+    run(&mut game, &mut driver);
+
+    // Changing theme after a shot doesn't fly the arrow again.
+    assert_eq!(driver.frames(), 8 + 2);
+    assert!(game.last_events().is_empty());
+}
+
+#[test]
+fn status_bar_shows_health_and_hunger() {
+    let mut game = Game::from_imported(parse_map("#####\n#@%.#\n#####", &Legend::default()).unwrap());
+    let player = game.player();
+    game.world().write::<Hunger>().get_mut(player).unwrap().food = 301;
+
+    let mut driver = HeadlessDriver::new(vec![Event::Wait], 16, 4);
+    run(&mut game, &mut driver);
+    assert_eq!(driver.screen(), "#####           \n#@%.#           \n#####           \nHP 30/30  Hungry");
+
+    let mut driver = HeadlessDriver::new(vec![Event::Movement((1, 0)), Event::PickUp, Event::Use(0)], 16, 4);
+    run(&mut game, &mut driver);
+    assert_eq!(game.world().read::<Hunger>().get(player).unwrap().food, 697);
+    assert!(driver.screen().ends_with("\nHP 30/30        "));
+}
+
+#[test]
+fn failed_actions_say_why_on_the_status_bar() {
+    let mut game = Game::from_imported(parse_map("#####\n#@..#\n#####", &Legend::default()).unwrap());
+    let mut driver = HeadlessDriver::new(vec![Event::Movement((0, -1))], 48, 4);
+    run(&mut game, &mut driver);
+    assert_eq!(game.messages(), vec![String::from("There is a wall in the way.")]);
+    assert_eq!(driver.screen().lines().last().unwrap().trim_end(),
+               "HP 30/30  Satiated  There is a wall in the way.");
+
+    // The next thing which works clears it.
+    let mut driver = HeadlessDriver::new(vec![Event::Wait], 48, 4);
+    run(&mut game, &mut driver);
+    assert!(game.messages().is_empty());
+    assert_eq!(driver.screen().lines().last().unwrap().trim_end(), "HP 30/30  Satiated");
+}
+
+#[test]
+fn starving_to_death_ends_the_game() {
+    let mut game = Game::from_imported(parse_map("#####\n#@..#\n#####", &Legend::default()).unwrap());
+    let player = game.player();
+    game.world().write::<Hunger>().get_mut(player).unwrap().food = 1;
+    game.world().write::<Fighter>().get_mut(player).unwrap().hp = 2;
+
+    let mut driver = HeadlessDriver::new(vec![Event::Wait; 5], 5, 4);
+    run(&mut game, &mut driver);
+    // Starving starts as soon as the food runs out, and 2 hp lasts two turns.
+    assert!(game.player_died());
+    assert!(!game.is_running());
+    assert_eq!(game.turns(), 2);
+    assert_eq!(driver.remaining(), 3);
+    assert_eq!(game.player_position(), None);
+    assert_eq!(driver.screen(), "#####\n#...#\n#####\n     ");
+}
+
+#[test]
+fn the_stranger_talks_to_you_and_friends_make_way() {
+    let mut game = Game::new(5);
+    let (glyph, (gx, gy)) = {
+        let world = game.world();
+        let (entities, names, positions) = (world.entities(), world.read::<BaseEntity>(), world.read::<Position>());
+        (&*entities, &names, &positions).join()
+            .find(|&(_, base, _)| base.name == "Mysterious Glyph")
+            .map(|(ent, _, pos)| (ent, (pos.x, pos.y)))
+            .unwrap()
+    };
+    let (px, py) = game.player_position().unwrap();
+    let towards = Event::Movement((gx - px, gy - py));
+
+    let mut driver = HeadlessDriver::new(vec![towards.clone()], 64, 33);
+    run(&mut game, &mut driver);
+    assert_eq!(game.last_results()[0].action, Action::Talk { target: glyph });
+    assert_eq!(game.player_position(), Some((px, py)));
+    assert!(driver.screen().contains("The Mysterious Glyph has nothing to say."));
+
+    game.world().write_resource::<Relationships>().set(Faction::Player, Faction::Strangers, Relation::Friendly);
+    game.step(towards);
+    assert_eq!(game.last_results()[0].action, Action::Swap { target: glyph });
+    assert_eq!(game.player_position(), Some((gx, gy)));
+}
+
+#[test]
+fn blind_players_only_see_what_is_next_to_them() {
+    let mut game = Game::from_imported(parse_map("#######\n#@...o#\n#######", &Legend::default()).unwrap());
+    let player = game.player();
+    let mut effects = StatusEffects::new();
+    effects.add(EffectKind::Blindness, 2);
+    game.world().write::<StatusEffects>().insert(player, effects);
+
+    let mut driver = HeadlessDriver::new(vec![Event::Wait], 7, 3);
+    run(&mut game, &mut driver);
+    assert_eq!(driver.screen(), "###    \n#@.    \n###    ");
+
+    // Once it wears off, everything is back.
+    let mut driver = HeadlessDriver::new(vec![Event::Wait], 7, 3);
+    run(&mut game, &mut driver);
+    assert_eq!(driver.screen(), "#######\n#@...o#\n#######");
+}